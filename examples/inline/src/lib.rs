@@ -4,8 +4,6 @@ use std::io;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[cfg(not(target_arch = "wasm32"))]
-use crossterm::event::EventStream;
 use futures::stream::StreamExt;
 use rand::distributions::Uniform;
 use rand::prelude::Distribution;
@@ -13,11 +11,12 @@ use ratatui::Viewport;
 use ratatui::prelude::*;
 use ratatui::widgets::*;
 #[cfg(target_arch = "wasm32")]
-use ratatui_xterm_js::EventStream;
-#[cfg(target_arch = "wasm32")]
 use ratatui_xterm_js::xterm::Theme;
 #[cfg(target_arch = "wasm32")]
-use ratatui_xterm_js::{TerminalHandle, XtermJsBackend, init_terminal, xterm::TerminalOptions};
+use ratatui_xterm_js::{
+    JsTerminal, Presentation, TerminalConfig, XtermJsBackend, init_terminal_with_config,
+    install_panic_hook, xterm::TerminalOptions,
+};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::spawn;
 use tokio::sync::mpsc;
@@ -36,8 +35,15 @@ const NUM_DOWNLOADS: usize = 10;
 type DownloadId = usize;
 type WorkerId = usize;
 
+#[cfg(target_arch = "wasm32")]
+pub type EventStream = ratatui_xterm_js::EventStream<JsTerminal>;
+
+/// Crossterm's event stream, converted to the terminput events used on the web.
+#[cfg(not(target_arch = "wasm32"))]
+pub type EventStream = futures::stream::BoxStream<'static, io::Result<terminput::Event>>;
+
 enum Event {
-    Input(crossterm::event::KeyEvent),
+    Input(terminput::KeyEvent),
     Tick,
     Resize,
     DownloadUpdate(WorkerId, DownloadId, f64),
@@ -87,6 +93,7 @@ struct Worker {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub async fn main() -> Result<(), JsError> {
+    // Logs panics with a stack trace to the console, also after install_panic_hook below
     console_error_panic_hook::set_once();
     let elem = web_sys::window()
         .unwrap()
@@ -95,7 +102,7 @@ pub async fn main() -> Result<(), JsError> {
         .get_element_by_id("terminal")
         .unwrap();
 
-    init_terminal(
+    let terminal = init_terminal_with_config(
         TerminalOptions::new()
             .with_rows(50)
             .with_cursor_blink(true)
@@ -109,21 +116,32 @@ pub async fn main() -> Result<(), JsError> {
                     .with_background("#000000"),
             ),
         elem.dyn_into().map_err(|e| JsError::new(&e.node_name()))?,
-    );
-    let handle = TerminalHandle::default();
-
-    run(handle, XtermJsBackend::new)
-        .await
-        .map_err(|e| JsError::new(&e.to_string()))?;
+        // Progress updates redraw on every tick, so only present the latest state per frame
+        TerminalConfig::new().with_presentation(Presentation::AnimationFrame),
+    )?;
+    // Show panics in the terminal from here on instead of leaving a frozen screen
+    install_panic_hook(&terminal);
+    run(
+        terminal.handle(),
+        XtermJsBackend::new,
+        terminal.event_stream(),
+    )
+    .await
+    .map_err(|e| JsError::new(&e.to_string()))?;
     Ok(())
 }
 
-pub async fn run<W, F, B>(out: W, create_backend: F) -> Result<(), Box<dyn Error>>
+pub async fn run<W, F, B>(
+    out: W,
+    create_backend: F,
+    events: EventStream,
+) -> Result<(), Box<dyn Error>>
 where
     W: io::Write,
     B: Backend,
     F: FnOnce(W) -> B,
 {
+    #[cfg(not(target_arch = "wasm32"))]
     crossterm::terminal::enable_raw_mode()?;
 
     let backend = create_backend(out);
@@ -136,7 +154,7 @@ where
     .unwrap();
 
     let (tx, rx) = mpsc::channel(32);
-    input_handling(tx.clone());
+    input_handling(tx.clone(), events);
     let mut workers = workers(tx);
     let mut downloads = downloads();
 
@@ -149,15 +167,15 @@ where
         .await
         .unwrap();
 
+    #[cfg(not(target_arch = "wasm32"))]
     crossterm::terminal::disable_raw_mode()?;
     terminal.clear().unwrap();
 
     Ok(())
 }
 
-fn input_handling(tx: mpsc::Sender<Event>) {
+fn input_handling(tx: mpsc::Sender<Event>, mut events: EventStream) {
     spawn(async move {
-        let mut events = EventStream::default();
         loop {
             tokio::select! {
                 _ = sleep(200) => {
@@ -166,10 +184,10 @@ fn input_handling(tx: mpsc::Sender<Event>) {
                 event = events.next() => {
                     if let Some(Ok(event)) = event {
                         match event {
-                            crossterm::event::Event::Key(key) => {
+                            terminput::Event::Key(key) => {
                                 tx.send(Event::Input(key)).await.ok();
                             }
-                            crossterm::event::Event::Resize(_, _) => {
+                            terminput::Event::Resize { .. } => {
                                 tx.send(Event::Resize).await.ok();
                             }
                             _ => {}
@@ -268,7 +286,7 @@ async fn run_app<B: Backend>(
 
         match rx.recv().await.unwrap() {
             Event::Input(event) => {
-                if event.code == crossterm::event::KeyCode::Char('q') {
+                if event.code == terminput::KeyCode::Char('q') {
                     break;
                 }
             }
@@ -2,19 +2,22 @@ use std::error::Error;
 use std::io;
 
 #[cfg(not(target_arch = "wasm32"))]
-use crossterm::event::EventStream;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+#[cfg(not(target_arch = "wasm32"))]
 use crossterm::execute;
+#[cfg(not(target_arch = "wasm32"))]
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use ratatui::prelude::*;
 use ratatui::widgets::*;
 #[cfg(target_arch = "wasm32")]
-use ratatui_xterm_js::EventStream;
-#[cfg(target_arch = "wasm32")]
 use ratatui_xterm_js::xterm::Theme;
 #[cfg(target_arch = "wasm32")]
-use ratatui_xterm_js::{TerminalHandle, XtermJsBackend, init_terminal};
+use ratatui_xterm_js::{
+    AutoFitOptions, FitMode, TerminalConfig, TerminalMode, XtermJsBackend,
+    init_terminal_with_config, install_panic_hook,
+};
+use terminput::{Event, KeyCode, KeyEventKind};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::{JsCast, JsValue, prelude::wasm_bindgen};
 
@@ -51,6 +54,7 @@ impl<'a> App<'a> {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen(start)]
 pub async fn main() -> Result<(), JsValue> {
+    // Logs panics with a stack trace to the console, also after install_panic_hook below
     console_error_panic_hook::set_once();
     let elem = web_sys::window()
         .unwrap()
@@ -59,7 +63,7 @@ pub async fn main() -> Result<(), JsValue> {
         .get_element_by_id("terminal")
         .unwrap();
 
-    init_terminal(
+    let terminal = init_terminal_with_config(
         ratatui_xterm_js::xterm::TerminalOptions::new()
             .with_rows(50)
             .with_cursor_blink(true)
@@ -73,41 +77,69 @@ pub async fn main() -> Result<(), JsValue> {
                     .with_background("#000000"),
             ),
         elem.dyn_into()?,
-    );
-
-    let handle = TerminalHandle::default();
-    run(handle, XtermJsBackend::new).await.unwrap();
+        TerminalConfig::new().with_fit_mode(FitMode::Auto(
+            AutoFitOptions::new().with_min_cols(20).with_min_rows(5),
+        )),
+    )?;
+    // Show panics in the terminal from here on instead of leaving a frozen screen
+    install_panic_hook(&terminal);
+
+    // xterm.js has no raw mode, so only the alternate screen and mouse capture are needed. The
+    // backend turns them off again when it's dropped.
+    let create_backend = |handle| {
+        let mut backend = XtermJsBackend::new(handle);
+        backend.enable_mode(TerminalMode::AlternateScreen).unwrap();
+        backend.enable_mode(TerminalMode::MouseCapture).unwrap();
+        backend
+    };
+    run(terminal.handle(), create_backend, terminal.event_stream())
+        .await
+        .unwrap();
     Ok(())
 }
 
-pub async fn run<W, F, B>(mut out: W, create_backend: F) -> Result<(), Box<dyn Error>>
+pub async fn run<W, F, B, E>(out: W, create_backend: F, events: E) -> Result<(), Box<dyn Error>>
 where
     W: io::Write,
     B: Backend + io::Write,
     F: FnOnce(W) -> B,
+    E: Stream<Item = io::Result<Event>> + Unpin,
 {
-    crossterm::terminal::enable_raw_mode().unwrap();
-
-    execute!(out, EnterAlternateScreen, EnableMouseCapture).unwrap();
     let backend = create_backend(out);
     let mut terminal = Terminal::new(backend).unwrap();
+    #[cfg(not(target_arch = "wasm32"))]
+    enter_screen(terminal.backend_mut()).unwrap();
 
     let app = App::new();
 
-    run_app(&mut terminal, app).await.unwrap();
-    crossterm::terminal::disable_raw_mode().unwrap();
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )
-    .unwrap();
+    run_app(&mut terminal, app, events).await.unwrap();
+    #[cfg(not(target_arch = "wasm32"))]
+    leave_screen(terminal.backend_mut()).unwrap();
     terminal.show_cursor().unwrap();
     Ok(())
 }
 
-async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App<'_>) -> io::Result<()> {
-    let mut events = EventStream::default();
+#[cfg(not(target_arch = "wasm32"))]
+fn enter_screen(out: &mut impl io::Write) -> io::Result<()> {
+    crossterm::terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, EnableMouseCapture)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn leave_screen(out: &mut impl io::Write) -> io::Result<()> {
+    crossterm::terminal::disable_raw_mode()?;
+    execute!(out, LeaveAlternateScreen, DisableMouseCapture)
+}
+
+async fn run_app<B, E>(
+    terminal: &mut Terminal<B>,
+    mut app: App<'_>,
+    mut events: E,
+) -> io::Result<()>
+where
+    B: Backend,
+    E: Stream<Item = io::Result<Event>> + Unpin,
+{
     loop {
         terminal.draw(|f| ui(f, &app))?;
         if let Some(Ok(event)) = events.next().await {
@@ -1,8 +1,18 @@
 use std::error::Error;
 use std::io;
 
+use crossterm::event::EventStream;
+use futures::{StreamExt, future};
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn Error>> {
     let stdout = io::stdout();
-    simple::run(stdout, ratatui::backend::CrosstermBackend::new).await
+    // Convert crossterm's events to the terminput events used by the app
+    let events = EventStream::new().filter_map(|event| {
+        future::ready(match event {
+            Ok(event) => terminput_crossterm::to_terminput(event).ok().map(Ok),
+            Err(e) => Some(Err(e)),
+        })
+    });
+    simple::run(stdout, ratatui::backend::CrosstermBackend::new, events).await
 }
@@ -0,0 +1,242 @@
+use std::io::{self, Write};
+
+use ratatui::backend::ClearType;
+use ratatui::style::{Color, Modifier};
+
+pub(crate) fn move_to(w: &mut impl Write, x: u16, y: u16) -> io::Result<()> {
+    write!(w, "\x1B[{};{}H", y.saturating_add(1), x.saturating_add(1))
+}
+
+pub(crate) fn clear(w: &mut impl Write, clear_type: ClearType) -> io::Result<()> {
+    w.write_all(match clear_type {
+        ClearType::All => b"\x1B[2J",
+        ClearType::AfterCursor => b"\x1B[J",
+        ClearType::BeforeCursor => b"\x1B[1J",
+        ClearType::CurrentLine => b"\x1B[2K",
+        ClearType::UntilNewLine => b"\x1B[K",
+    })
+}
+
+/// Scrolls the rows from `first_row` to `last_row` (inclusive) up by `lines` lines.
+#[cfg(feature = "scrolling-regions")]
+pub(crate) fn scroll_up_in_region(
+    w: &mut impl Write,
+    first_row: u16,
+    last_row: u16,
+    lines: u16,
+) -> io::Result<()> {
+    scroll_in_region(w, first_row, last_row, lines, 'S')
+}
+
+/// Scrolls the rows from `first_row` to `last_row` (inclusive) down by `lines` lines.
+#[cfg(feature = "scrolling-regions")]
+pub(crate) fn scroll_down_in_region(
+    w: &mut impl Write,
+    first_row: u16,
+    last_row: u16,
+    lines: u16,
+) -> io::Result<()> {
+    scroll_in_region(w, first_row, last_row, lines, 'T')
+}
+
+#[cfg(feature = "scrolling-regions")]
+fn scroll_in_region(
+    w: &mut impl Write,
+    first_row: u16,
+    last_row: u16,
+    lines: u16,
+    direction: char,
+) -> io::Result<()> {
+    if lines == 0 {
+        return Ok(());
+    }
+    // Limit scrolling to the region, then reset the region to the whole screen
+    write!(
+        w,
+        "\x1B[{};{}r\x1B[{lines}{direction}\x1B[r",
+        first_row.saturating_add(1),
+        last_row.saturating_add(1)
+    )
+}
+
+/// Writes a single SGR sequence with the given parameters. No parameters reset all attributes.
+pub(crate) fn sgr(w: &mut impl Write, params: &[u16]) -> io::Result<()> {
+    w.write_all(b"\x1B[")?;
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            w.write_all(b";")?;
+        }
+        write!(w, "{param}")?;
+    }
+    w.write_all(b"m")
+}
+
+/// Returns the length in bytes of the SGR sequence for `params`.
+pub(crate) fn sgr_len(params: &[u16]) -> usize {
+    // ESC [ and m, plus the separators
+    3 + params.len().saturating_sub(1) + params.iter().map(|&p| digits(p)).sum::<usize>()
+}
+
+/// Returns the number of decimal digits in `n`.
+pub(crate) fn digits(n: u16) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+/// Adds the SGR parameters that set the foreground color.
+pub(crate) fn push_fg(params: &mut Vec<u16>, color: Color) {
+    match color {
+        // 90-97 for the bright colors. The base colors use the palette form, since some
+        // terminals brighten 30-37 in bold text.
+        Color::Reset => params.push(39),
+        color => match ansi_index(color) {
+            Some(i @ 8..) => params.push(90 + u16::from(i - 8)),
+            _ => push_extended(params, color, 38),
+        },
+    }
+}
+
+/// Adds the SGR parameters that set the background color.
+pub(crate) fn push_bg(params: &mut Vec<u16>, color: Color) {
+    match color {
+        Color::Reset => params.push(49),
+        color => match ansi_index(color) {
+            Some(i @ 8..) => params.push(100 + u16::from(i - 8)),
+            Some(i) => params.push(40 + u16::from(i)),
+            None => push_extended(params, color, 48),
+        },
+    }
+}
+
+#[cfg(feature = "underline-color")]
+pub(crate) fn push_underline_color(params: &mut Vec<u16>, color: Color) {
+    match color {
+        Color::Reset => params.push(59),
+        color => push_extended(params, color, 58),
+    }
+}
+
+/// Adds the `base;5;index` or `base;2;r;g;b` form of `color`.
+fn push_extended(params: &mut Vec<u16>, color: Color, base: u16) {
+    match color {
+        Color::Rgb(r, g, b) => params.extend([base, 2, r.into(), g.into(), b.into()]),
+        Color::Indexed(i) => params.extend([base, 5, i.into()]),
+        color => match ansi_index(color) {
+            Some(i) => params.extend([base, 5, i.into()]),
+            None => unreachable!("{color:?} has no extended form"),
+        },
+    }
+}
+
+/// Returns the palette index of a named color, or `None` for other colors.
+pub(crate) fn ansi_index(color: Color) -> Option<u8> {
+    Some(match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Reset | Color::Rgb(..) | Color::Indexed(_) => return None,
+    })
+}
+
+/// Adds the SGR parameters that switch the text attributes from `from` to `to`.
+pub(crate) fn push_modifier_changes(params: &mut Vec<u16>, from: Modifier, to: Modifier) {
+    let removed = from - to;
+    // Some attributes can only be turned off together, so the ones to keep are added back
+    let mut restored = Modifier::empty();
+    if removed.intersects(Modifier::BOLD | Modifier::DIM) {
+        params.push(22);
+        restored |= to & (Modifier::BOLD | Modifier::DIM);
+    }
+    if removed.intersects(Modifier::SLOW_BLINK | Modifier::RAPID_BLINK) {
+        params.push(25);
+        restored |= to & (Modifier::SLOW_BLINK | Modifier::RAPID_BLINK);
+    }
+    for (modifier, param) in [
+        (Modifier::ITALIC, 23),
+        (Modifier::UNDERLINED, 24),
+        (Modifier::REVERSED, 27),
+        (Modifier::HIDDEN, 28),
+        (Modifier::CROSSED_OUT, 29),
+    ] {
+        if removed.contains(modifier) {
+            params.push(param);
+        }
+    }
+
+    let added = (to - from) | restored;
+    for (modifier, param) in [
+        (Modifier::BOLD, 1),
+        (Modifier::DIM, 2),
+        (Modifier::ITALIC, 3),
+        (Modifier::UNDERLINED, 4),
+        (Modifier::SLOW_BLINK, 5),
+        (Modifier::RAPID_BLINK, 6),
+        (Modifier::REVERSED, 7),
+        (Modifier::HIDDEN, 8),
+        (Modifier::CROSSED_OUT, 9),
+    ] {
+        if added.contains(modifier) {
+            params.push(param);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::{ansi_index, push_bg, push_fg};
+
+    fn fg(color: Color) -> Vec<u16> {
+        let mut params = Vec::new();
+        push_fg(&mut params, color);
+        params
+    }
+
+    fn bg(color: Color) -> Vec<u16> {
+        let mut params = Vec::new();
+        push_bg(&mut params, color);
+        params
+    }
+
+    #[test]
+    fn indexes_only_named_colors() {
+        assert_eq!(ansi_index(Color::Black), Some(0));
+        assert_eq!(ansi_index(Color::Gray), Some(7));
+        assert_eq!(ansi_index(Color::DarkGray), Some(8));
+        assert_eq!(ansi_index(Color::White), Some(15));
+        assert_eq!(ansi_index(Color::Reset), None);
+        assert_eq!(ansi_index(Color::Indexed(3)), None);
+        assert_eq!(ansi_index(Color::Rgb(1, 2, 3)), None);
+    }
+
+    #[test]
+    fn encodes_foreground_colors() {
+        assert_eq!(fg(Color::Reset), [39]);
+        assert_eq!(fg(Color::Red), [38, 5, 1]);
+        assert_eq!(fg(Color::LightRed), [91]);
+        assert_eq!(fg(Color::Indexed(200)), [38, 5, 200]);
+        assert_eq!(fg(Color::Rgb(1, 2, 3)), [38, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn encodes_background_colors() {
+        assert_eq!(bg(Color::Reset), [49]);
+        assert_eq!(bg(Color::Red), [41]);
+        assert_eq!(bg(Color::White), [107]);
+        assert_eq!(bg(Color::Indexed(200)), [48, 5, 200]);
+        assert_eq!(bg(Color::Rgb(1, 2, 3)), [48, 2, 1, 2, 3]);
+    }
+}
@@ -1,9 +1,6 @@
-//! This module provides the `CrosstermBackend` implementation for the `Backend` trait.
-//! It uses the `crossterm` crate to interact with the terminal.
-//!
-//!
-//! [`Backend`]: trait.Backend.html
-//! [`CrosstermBackend`]: struct.CrosstermBackend.html
+//! This module provides the [`XtermJsBackend`] implementation for ratatui's [`Backend`] trait.
+//! It writes escape sequences itself, so it doesn't depend on a native terminal library.
+//! Frames are encoded by the `FrameEncoder`, which keeps the output small.
 
 use std::io::{self, Write};
 
@@ -11,122 +8,560 @@ use ratatui::backend::{Backend, ClearType, WindowSize};
 use ratatui::buffer::Cell;
 use ratatui::layout::{Position, Size};
 
-use crate::js_terminal::{TerminalHandle, cursor_position};
-use crate::window_size;
+use crate::encoder::FrameEncoder;
+use crate::{Host, Hyperlinks, TerminalHandle, TerminalMode, ansi};
 
-/// A backend implementation using the `crossterm` crate.
+pub(crate) const BEGIN_SYNCHRONIZED_UPDATE: &str = "\x1B[?2026h";
+pub(crate) const END_SYNCHRONIZED_UPDATE: &str = "\x1B[?2026l";
+
+/// A ratatui backend that draws to a [`Host`] through a [`TerminalHandle`].
+///
+/// Drawing, cursor and clearing commands are encoded as ANSI escape sequences and buffered in
+/// the handle until the backend is flushed.
 ///
-/// The `CrosstermBackend` struct is a wrapper around a type implementing `Write`, which
-/// is used to send commands to the terminal. It provides methods for drawing content,
-/// manipulating the cursor, and clearing the terminal screen.
+/// The backend remembers the attributes it left the terminal in and what it drew on the screen.
+/// Frames only send the attributes that change, move the cursor with the shortest sequence, and
+/// erase or repeat runs of equal cells where that's shorter.
+/// [`last_frame_bytes`](Self::last_frame_bytes) reports how large the last frame was.
+///
+/// Modes turned on with [`enable_mode`](Self::enable_mode) are turned off again by
+/// [`restore_modes`](Self::restore_modes) or when the backend is dropped, so an app that exits
+/// early doesn't leave the terminal in the alternate screen or with the mouse captured.
 ///
 /// # Example
 ///
 /// ```rust
-/// use ratatui::backend::{Backend, CrosstermBackend};
+/// use ratatui::backend::Backend;
+/// use ratatui_xterm_js::{MemoryTerminal, XtermJsBackend};
 ///
 /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
-/// let buffer = std::io::stdout();
-/// let mut backend = CrosstermBackend::new(buffer);
+/// let terminal = MemoryTerminal::new(80, 24);
+/// let mut backend = XtermJsBackend::new(terminal.handle());
 /// backend.clear()?;
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Default)]
-pub struct XtermJsBackend {
-    inner: ratatui::backend::CrosstermBackend<TerminalHandle>,
+pub struct XtermJsBackend<H: Host> {
+    handle: TerminalHandle<H>,
+    synchronized_output: bool,
+    /// Whether a synchronized update was started and hasn't been ended by a flush yet.
+    in_update: bool,
+    encoder: FrameEncoder,
+    /// The handle's byte count when the current frame started.
+    frame_start: usize,
+    /// Whether the current frame was flushed, so the next drawing call starts a new one.
+    frame_flushed: bool,
+    last_frame_bytes: usize,
+    /// The modes this backend turned on, in the order they were turned on.
+    modes: Vec<TerminalMode>,
+    links: Hyperlinks,
 }
 
-impl XtermJsBackend {
-    /// Creates a new `CrosstermBackend` with the given buffer.
-    pub fn new(handle: TerminalHandle) -> Self {
+impl<H: Host> XtermJsBackend<H> {
+    /// Creates a new `XtermJsBackend` that writes to the given handle.
+    pub fn new(handle: TerminalHandle<H>) -> Self {
         Self {
-            inner: ratatui::backend::CrosstermBackend::new(handle),
+            handle,
+            synchronized_output: false,
+            in_update: false,
+            encoder: FrameEncoder::new(),
+            frame_start: 0,
+            frame_flushed: true,
+            last_frame_bytes: 0,
+            modes: Vec::new(),
+            links: Hyperlinks::default(),
+        }
+    }
+
+    /// Wraps each frame in a synchronized update (DEC mode 2026), so the terminal shows it all at
+    /// once instead of tearing partway through a large redraw.
+    ///
+    /// The update starts with the first drawing or clearing call and ends at the next flush,
+    /// which [`Terminal::draw`](ratatui::Terminal::draw) does after every frame. Terminals that
+    /// don't support the mode ignore it. Use
+    /// [`TerminalQueries::supports_synchronized_output`](crate::TerminalQueries::supports_synchronized_output)
+    /// to check.
+    pub fn with_synchronized_output(mut self, enabled: bool) -> Self {
+        self.synchronized_output = enabled;
+        self
+    }
+
+    /// Enables or disables synchronized output. See
+    /// [`with_synchronized_output`](Self::with_synchronized_output).
+    pub fn set_synchronized_output(&mut self, enabled: bool) {
+        self.synchronized_output = enabled;
+    }
+
+    /// Returns the terminal this backend draws to.
+    pub fn terminal(&self) -> &H {
+        self.handle.terminal()
+    }
+
+    /// Waits until the terminal is ready to accept more output.
+    ///
+    /// Awaiting this before each [`Terminal::draw`](ratatui::Terminal::draw) keeps a tight render
+    /// loop from queueing frames faster than the terminal can display them.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.handle.writable().await
+    }
+
+    /// Returns the hyperlinks for the next frame.
+    ///
+    /// Add links to it while rendering, and they're drawn with the frame's cells. See
+    /// [`Hyperlinks`].
+    pub fn hyperlinks(&self) -> Hyperlinks {
+        self.links.clone()
+    }
+
+    /// Returns the number of bytes written for the last frame.
+    ///
+    /// A frame starts with the first drawing or clearing call after a flush and includes
+    /// everything written until the next flush, so for
+    /// [`Terminal::draw`](ratatui::Terminal::draw) it covers the cells as well as the cursor.
+    /// Flushes without a drawing or clearing call in between keep the count.
+    pub fn last_frame_bytes(&self) -> usize {
+        self.last_frame_bytes
+    }
+
+    /// Turns on a terminal mode and remembers to turn it off in
+    /// [`restore_modes`](Self::restore_modes).
+    pub fn enable_mode(&mut self, mode: TerminalMode) -> io::Result<()> {
+        self.write_mode(mode, true)?;
+        self.handle.flush()
+    }
+
+    /// Turns off a terminal mode.
+    pub fn disable_mode(&mut self, mode: TerminalMode) -> io::Result<()> {
+        self.write_mode(mode, false)?;
+        self.handle.flush()
+    }
+
+    /// Returns whether this backend turned on `mode` and hasn't turned it off since.
+    pub fn is_mode_enabled(&self, mode: TerminalMode) -> bool {
+        self.modes.contains(&mode)
+    }
+
+    /// Turns off every mode this backend turned on, in the reverse order, and flushes.
+    ///
+    /// This is also done when the backend is dropped.
+    pub fn restore_modes(&mut self) -> io::Result<()> {
+        while let Some(&mode) = self.modes.last() {
+            self.write_mode(mode, false)?;
         }
+        self.flush_update()
+    }
+
+    fn write_mode(&mut self, mode: TerminalMode, enabled: bool) -> io::Result<()> {
+        if enabled {
+            self.handle.write_all(mode.enable_sequence().as_bytes())?;
+            if !self.modes.contains(&mode) {
+                self.modes.push(mode);
+            }
+        } else {
+            self.handle.write_all(mode.disable_sequence().as_bytes())?;
+            self.modes.retain(|&m| m != mode);
+        }
+        if mode == TerminalMode::AlternateScreen {
+            // The other screen has different contents
+            self.encoder.invalidate();
+        }
+        Ok(())
+    }
+
+    fn begin_update(&mut self) -> io::Result<()> {
+        if self.frame_flushed {
+            self.frame_start = self.handle.bytes_written();
+            self.frame_flushed = false;
+        }
+        if self.synchronized_output && !self.in_update {
+            self.handle
+                .write_all(BEGIN_SYNCHRONIZED_UPDATE.as_bytes())?;
+            self.in_update = true;
+        }
+        Ok(())
+    }
+
+    fn flush_update(&mut self) -> io::Result<()> {
+        if self.in_update {
+            self.handle.write_all(END_SYNCHRONIZED_UPDATE.as_bytes())?;
+            self.in_update = false;
+        }
+        // Flushes between frames don't start a frame, so they keep the last frame's size
+        if !self.frame_flushed {
+            self.last_frame_bytes = self.handle.bytes_written() - self.frame_start;
+            self.frame_flushed = true;
+        }
+        self.handle.flush()
     }
 }
 
-impl Write for XtermJsBackend {
+impl<H: Host> Write for XtermJsBackend<H> {
     /// Writes a buffer of bytes to the underlying buffer.
+    ///
+    /// The backend can't tell what the bytes do, so it forgets what it drew on the screen.
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        self.encoder.invalidate();
+        self.handle.write(buf)
     }
 
     /// Flushes the underlying buffer.
     fn flush(&mut self) -> io::Result<()> {
-        io::Write::flush(&mut self.inner)
+        self.flush_update()
     }
 }
 
-impl Backend for XtermJsBackend {
+impl<H: Host> Backend for XtermJsBackend<H> {
     fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
     where
         I: Iterator<Item = (u16, u16, &'a Cell)>,
     {
-        self.inner.draw(content)
+        self.begin_update()?;
+        let size = self.size()?;
+        let repeat = self.terminal().supports_repeat();
+        let links = self.links.take();
+        self.encoder
+            .encode(&mut self.handle, size, repeat, links, content)
     }
 
     fn hide_cursor(&mut self) -> io::Result<()> {
-        self.inner.hide_cursor()
+        self.enable_mode(TerminalMode::HiddenCursor)
     }
 
     fn show_cursor(&mut self) -> io::Result<()> {
-        self.inner.show_cursor()
+        self.disable_mode(TerminalMode::HiddenCursor)
     }
 
+    /// Flushes the output and returns the cursor position reported by the terminal.
+    ///
+    /// `JsTerminal` makes xterm.js parse the flushed output first, which relies on xterm.js
+    /// internals. If those are missing, the position may be stale. Await
+    /// `JsTerminal::synced_cursor_position` instead to get the position after xterm.js has parsed
+    /// everything.
     fn get_cursor_position(&mut self) -> io::Result<Position> {
-        let (x, y) = cursor_position()?;
+        // Output that hasn't been flushed yet may still move the cursor
+        self.handle.flush()?;
+        let (x, y) = self.terminal().cursor_position()?;
         Ok(Position::new(x, y))
     }
 
     fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
-        self.inner.set_cursor_position(position)
+        let Position { x, y } = position.into();
+        ansi::move_to(&mut self.handle, x, y)?;
+        self.handle.flush()
     }
 
     fn clear(&mut self) -> io::Result<()> {
-        self.inner.clear()
+        self.clear_region(ClearType::All)
     }
 
     fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
-        self.inner.clear_region(clear_type)
+        self.begin_update()?;
+        ansi::clear(&mut self.handle, clear_type)?;
+        if clear_type == ClearType::All {
+            self.encoder.cleared();
+        } else {
+            self.encoder.partially_cleared();
+        }
+        self.handle.flush()
     }
 
     fn append_lines(&mut self, n: u16) -> io::Result<()> {
-        self.inner.append_lines(n)
+        for _ in 0..n {
+            self.handle.write_all(b"\n")?;
+        }
+        self.encoder.invalidate();
+        self.handle.flush()
     }
 
     fn size(&self) -> io::Result<Size> {
-        let (width, height) = crate::js_terminal::size()?;
+        let (width, height) = self.terminal().size()?;
         Ok(Size::new(width, height))
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        io::Write::flush(&mut self.inner)
+        self.flush_update()
     }
 
     #[cfg(feature = "scrolling-regions")]
     fn scroll_region_up(&mut self, region: std::ops::Range<u16>, amount: u16) -> io::Result<()> {
-        self.inner.scroll_region_up(region, amount)
+        ansi::scroll_up_in_region(
+            &mut self.handle,
+            region.start,
+            region.end.saturating_sub(1),
+            amount,
+        )?;
+        self.encoder.scrolled(region, amount, true);
+        self.handle.flush()
     }
 
     #[cfg(feature = "scrolling-regions")]
     fn scroll_region_down(&mut self, region: std::ops::Range<u16>, amount: u16) -> io::Result<()> {
-        self.inner.scroll_region_down(region, amount)
+        ansi::scroll_down_in_region(
+            &mut self.handle,
+            region.start,
+            region.end.saturating_sub(1),
+            amount,
+        )?;
+        self.encoder.scrolled(region, amount, false);
+        self.handle.flush()
     }
 
     fn window_size(&mut self) -> io::Result<WindowSize> {
-        let crossterm::terminal::WindowSize {
-            columns,
-            rows,
-            width,
-            height,
-        } = window_size()?;
-        Ok(WindowSize {
-            columns_rows: Size {
-                width: columns,
-                height: rows,
-            },
-            pixels: Size { width, height },
-        })
+        self.terminal().window_size()
+    }
+}
+
+impl<H: Host> Drop for XtermJsBackend<H> {
+    fn drop(&mut self) {
+        // The terminal may already be gone, in which case there's nothing to restore
+        self.restore_modes().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::task::{Context, Poll};
+
+    use ratatui::Terminal;
+    use ratatui::backend::{Backend, WindowSize};
+    use ratatui::buffer::Cell;
+    use ratatui::layout::{Position, Size};
+    use ratatui::widgets::Paragraph;
+
+    use super::XtermJsBackend;
+    use crate::{Host, MemoryTerminal, TerminalHandle, TerminalInput, TerminalMode};
+
+    fn backend(cols: u16, rows: u16) -> (MemoryTerminal, XtermJsBackend<MemoryTerminal>) {
+        let terminal = MemoryTerminal::new(cols, rows);
+        let backend = XtermJsBackend::new(terminal.handle());
+        (terminal, backend)
+    }
+
+    /// Draws each `(x, text)` pair on the first row and flushes.
+    fn draw_text<H: Host>(backend: &mut XtermJsBackend<H>, texts: &[(u16, &str)]) {
+        let cells: Vec<_> = texts
+            .iter()
+            .flat_map(|&(x, text)| text.chars().zip(x..))
+            .map(|(c, x)| {
+                let mut cell = Cell::default();
+                cell.set_char(c);
+                (x, 0, cell)
+            })
+            .collect();
+        backend
+            .draw(cells.iter().map(|(x, y, cell)| (*x, *y, cell)))
+            .unwrap();
+        Backend::flush(backend).unwrap();
+    }
+
+    /// Checks that the backend writes through the cells it drew, until `forget` makes it
+    /// forget them.
+    fn assert_forgets_the_screen(forget: impl FnOnce(&mut XtermJsBackend<MemoryTerminal>)) {
+        let (terminal, mut backend) = backend(10, 2);
+        draw_text(&mut backend, &[(0, "abc")]);
+        terminal.take_output();
+        draw_text(&mut backend, &[(0, "x"), (2, "y")]);
+        assert_eq!(terminal.take_output(), "\x1B[Hxby");
+
+        forget(&mut backend);
+        terminal.take_output();
+        draw_text(&mut backend, &[(0, "x"), (2, "y")]);
+        assert_eq!(terminal.take_output(), "\x1B[Hx\x1B[Cy");
+    }
+
+    /// A terminal that understands REP.
+    struct RepeatingTerminal(MemoryTerminal);
+
+    impl Host for RepeatingTerminal {
+        fn write(&self, data: &str) -> io::Result<()> {
+            self.0.write(data)
+        }
+
+        fn window_size(&self) -> io::Result<WindowSize> {
+            self.0.window_size()
+        }
+
+        fn cursor_position(&self) -> io::Result<(u16, u16)> {
+            self.0.cursor_position()
+        }
+
+        fn supports_repeat(&self) -> bool {
+            true
+        }
+
+        fn poll_input(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>> {
+            self.0.poll_input(cx)
+        }
+    }
+
+    #[test]
+    fn draws_changed_cells() {
+        let (terminal, backend) = backend(10, 2);
+        let mut ratatui = Terminal::new(backend).unwrap();
+        ratatui
+            .draw(|frame| frame.render_widget(Paragraph::new("hi"), frame.area()))
+            .unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[Hhi\x1B[?25l");
+
+        ratatui
+            .draw(|frame| frame.render_widget(Paragraph::new("ho"), frame.area()))
+            .unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[1;2Ho\x1B[?25l");
+    }
+
+    #[test]
+    fn moves_and_reads_the_cursor() {
+        let (terminal, mut backend) = backend(10, 2);
+        backend.set_cursor_position((3, 1)).unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[2;4H");
+
+        terminal.set_cursor_position(5, 1);
+        assert_eq!(backend.get_cursor_position().unwrap(), Position::new(5, 1));
+    }
+
+    #[test]
+    fn reports_the_terminal_size() {
+        let (terminal, backend) = backend(10, 2);
+        assert_eq!(backend.size().unwrap(), Size::new(10, 2));
+        terminal.resize(20, 5);
+        assert_eq!(backend.size().unwrap(), Size::new(20, 5));
+
+        let mut ratatui = Terminal::new(backend).unwrap();
+        let frame = ratatui.draw(|_| {}).unwrap();
+        assert_eq!(frame.area.as_size(), Size::new(20, 5));
+    }
+
+    #[test]
+    fn clears_and_appends_lines() {
+        let (terminal, mut backend) = backend(10, 2);
+        backend.clear().unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[2J");
+        backend.append_lines(2).unwrap();
+        assert_eq!(terminal.take_output(), "\n\n");
+    }
+
+    #[test]
+    fn hides_and_shows_the_cursor() {
+        let (terminal, mut backend) = backend(10, 2);
+        backend.hide_cursor().unwrap();
+        backend.show_cursor().unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[?25l\x1B[?25h");
+    }
+
+    #[test]
+    fn forgets_the_screen_after_appending_lines() {
+        assert_forgets_the_screen(|backend| backend.append_lines(1).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "scrolling-regions")]
+    fn forgets_the_screen_after_scrolling() {
+        assert_forgets_the_screen(|backend| backend.scroll_region_up(0..2, 1).unwrap());
+        assert_forgets_the_screen(|backend| backend.scroll_region_down(0..2, 1).unwrap());
+    }
+
+    #[test]
+    fn forgets_the_screen_after_raw_writes() {
+        assert_forgets_the_screen(|backend| {
+            backend.write_all(b"\x1B[2;1Hz").unwrap();
+            Write::flush(backend).unwrap();
+        });
+    }
+
+    #[test]
+    fn repeats_runs_when_the_terminal_supports_it() {
+        let terminal = MemoryTerminal::new(20, 1);
+        let mut repeating =
+            XtermJsBackend::new(TerminalHandle::new(RepeatingTerminal(terminal.clone())));
+        draw_text(&mut repeating, &[(0, "aaaaaaaaaa")]);
+        assert_eq!(terminal.take_output(), "\x1B[Ha\x1B[9b");
+
+        let (terminal, mut backend) = backend(20, 1);
+        draw_text(&mut backend, &[(0, "aaaaaaaaaa")]);
+        assert_eq!(terminal.take_output(), "\x1B[Haaaaaaaaaa");
+    }
+
+    #[test]
+    fn counts_the_bytes_of_the_last_frame() {
+        let (terminal, mut backend) = backend(10, 2);
+        draw_text(&mut backend, &[(0, "abc")]);
+        assert_eq!(terminal.take_output(), "\x1B[Habc");
+        assert_eq!(backend.last_frame_bytes(), 6);
+
+        // Flushing again doesn't start a new frame
+        Backend::flush(&mut backend).unwrap();
+        backend.restore_modes().unwrap();
+        assert_eq!(backend.last_frame_bytes(), 6);
+
+        // The synchronized update is part of the frame
+        backend.set_synchronized_output(true);
+        draw_text(&mut backend, &[(0, "x")]);
+        assert_eq!(terminal.take_output(), "\x1B[?2026h\x1B[Hx\x1B[?2026l");
+        assert_eq!(backend.last_frame_bytes(), 20);
+    }
+
+    #[test]
+    fn wraps_each_frame_in_a_synchronized_update() {
+        let (terminal, backend) = backend(10, 2);
+        let mut backend = backend.with_synchronized_output(true);
+        backend.clear().unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[?2026h\x1B[2J");
+        draw_text(&mut backend, &[(0, "a")]);
+        assert_eq!(terminal.take_output(), "\x1B[Ha\x1B[?2026l");
+
+        // Flushing without drawing doesn't start an update
+        Backend::flush(&mut backend).unwrap();
+        assert_eq!(terminal.take_output(), "");
+
+        backend.set_synchronized_output(false);
+        draw_text(&mut backend, &[(0, "b")]);
+        assert_eq!(terminal.take_output(), "\x1B[Hb");
+    }
+
+    #[test]
+    fn restores_modes_in_reverse_order() {
+        let (terminal, mut backend) = backend(10, 2);
+        backend.enable_mode(TerminalMode::AlternateScreen).unwrap();
+        backend.enable_mode(TerminalMode::BracketedPaste).unwrap();
+        backend.enable_mode(TerminalMode::FocusReporting).unwrap();
+        // Enabling a mode twice only restores it once
+        backend.enable_mode(TerminalMode::BracketedPaste).unwrap();
+        backend.disable_mode(TerminalMode::FocusReporting).unwrap();
+        assert_eq!(
+            terminal.take_output(),
+            "\x1B[?1049h\x1B[?2004h\x1B[?1004h\x1B[?2004h\x1B[?1004l"
+        );
+        assert!(backend.is_mode_enabled(TerminalMode::BracketedPaste));
+        assert!(!backend.is_mode_enabled(TerminalMode::FocusReporting));
+
+        backend.restore_modes().unwrap();
+        assert_eq!(terminal.take_output(), "\x1B[?2004l\x1B[?1049l");
+        assert!(!backend.is_mode_enabled(TerminalMode::AlternateScreen));
+        backend.restore_modes().unwrap();
+        assert_eq!(terminal.take_output(), "");
+    }
+
+    #[test]
+    fn restores_modes_when_dropped() {
+        let (terminal, mut backend) = backend(10, 2);
+        backend.enable_mode(TerminalMode::MouseCapture).unwrap();
+        backend.hide_cursor().unwrap();
+        terminal.take_output();
+        drop(backend);
+        assert_eq!(
+            terminal.take_output(),
+            "\x1B[?25h\x1B[?1006l\x1B[?1015l\x1B[?1003l\x1B[?1002l\x1B[?1000l"
+        );
+    }
+
+    #[test]
+    fn forgets_the_screen_when_switching_screens() {
+        assert_forgets_the_screen(|backend| {
+            backend.enable_mode(TerminalMode::AlternateScreen).unwrap();
+        });
+        assert_forgets_the_screen(|backend| {
+            backend.disable_mode(TerminalMode::AlternateScreen).unwrap();
+        });
     }
 }
@@ -0,0 +1,137 @@
+const ESC: u8 = b'\x1B';
+const BEL: u8 = b'\x07';
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// Inside a CSI sequence, which ends with a byte in `0x40..=0x7E`.
+    Csi,
+    /// Inside an OSC, DCS, APC, PM or SOS string, which ends with BEL or ST.
+    String,
+    /// Saw ESC inside a string, which may start ST.
+    StringEscape,
+}
+
+/// Splits `data` into chunks of at most `max_len` bytes without cutting through an escape
+/// sequence or a UTF-8 character.
+///
+/// A single escape sequence longer than `max_len` is kept whole, so chunks may exceed the limit
+/// in that case.
+pub(crate) fn split_chunks(data: &str, max_len: usize) -> Vec<&str> {
+    let max_len = max_len.max(1);
+    let bytes = data.as_bytes();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    // The last position where the chunk may end
+    let mut boundary = 0;
+    let mut state = State::Ground;
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        if state == State::Ground && data.is_char_boundary(i) {
+            boundary = i;
+        }
+        if i - start >= max_len && boundary > start {
+            chunks.push(&data[start..boundary]);
+            start = boundary;
+        }
+        state = match (state, byte) {
+            (State::Ground, ESC) => State::Escape,
+            (State::Ground, _) => State::Ground,
+            (State::Escape, b'[') => State::Csi,
+            (State::Escape, b']' | b'P' | b'_' | b'^' | b'X') => State::String,
+            // Intermediate bytes are followed by the final byte of the sequence
+            (State::Escape, 0x20..=0x2F) => State::Escape,
+            (State::Escape, _) => State::Ground,
+            (State::Csi, 0x40..=0x7E) => State::Ground,
+            (State::Csi, _) => State::Csi,
+            (State::String, BEL) => State::Ground,
+            (State::String, ESC) => State::StringEscape,
+            (State::String, _) => State::String,
+            (State::StringEscape, b'\\') => State::Ground,
+            (State::StringEscape, ESC) => State::StringEscape,
+            (State::StringEscape, _) => State::String,
+        };
+    }
+    if start < bytes.len() {
+        chunks.push(&data[start..]);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_chunks;
+
+    #[test]
+    fn keeps_short_data_whole() {
+        assert_eq!(split_chunks("abc", 3), ["abc"]);
+        assert!(split_chunks("", 3).is_empty());
+    }
+
+    #[test]
+    fn splits_plain_text_at_the_limit() {
+        assert_eq!(split_chunks("abcdefg", 3), ["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn does_not_split_csi_sequences() {
+        assert_eq!(
+            split_chunks("ab\x1B[38;5;1mcd", 4),
+            ["ab", "\x1B[38;5;1m", "cd"]
+        );
+    }
+
+    #[test]
+    fn does_not_split_osc_sequences() {
+        // Terminated by BEL
+        assert_eq!(
+            split_chunks("a\x1B]8;;https://x\x07b", 4),
+            ["a", "\x1B]8;;https://x\x07", "b"]
+        );
+        // Terminated by ST, whose ESC doesn't end the sequence on its own
+        assert_eq!(
+            split_chunks("a\x1B]0;title\x1B\\b", 4),
+            ["a", "\x1B]0;title\x1B\\", "b"]
+        );
+    }
+
+    #[test]
+    fn does_not_split_dcs_sequences() {
+        assert_eq!(
+            split_chunks("a\x1BP1$r0m\x1B\\bcd", 4),
+            ["a", "\x1BP1$r0m\x1B\\", "bcd"]
+        );
+    }
+
+    #[test]
+    fn ends_strings_at_bel_or_st_only() {
+        // A BEL or the final byte of a CSI inside the string doesn't end it
+        let data = "\x1B]0;a[m\x07b\x1B]0;c\x1B[\x1B\\d";
+        assert_eq!(
+            split_chunks(data, 1),
+            ["\x1B]0;a[m\x07", "b", "\x1B]0;c\x1B[\x1B\\", "d"]
+        );
+    }
+
+    #[test]
+    fn does_not_split_characters() {
+        assert_eq!(split_chunks("aé€😀", 2), ["a", "é", "€", "😀"]);
+        assert_eq!(split_chunks("😀😀", 5), ["😀", "😀"]);
+    }
+
+    #[test]
+    fn keeps_sequences_longer_than_the_limit_whole() {
+        let link = "\x1B]8;;https://example.com\x1B\\";
+        let data = format!("ab{link}cd");
+        assert_eq!(split_chunks(&data, 2), ["ab", link, "cd"]);
+    }
+
+    #[test]
+    fn chunks_join_back_into_the_data() {
+        let data = "x\x1B[1;31mhé\x1B]8;;u\x07😀\x1B(B\x1BP$q m\x1B\\z";
+        for max_len in 0..data.len() + 2 {
+            assert_eq!(split_chunks(data, max_len).concat(), data, "{max_len}");
+        }
+    }
+}
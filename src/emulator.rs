@@ -0,0 +1,228 @@
+use std::cell::{Ref, RefCell};
+use std::io;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use ratatui::backend::WindowSize;
+use ratatui::layout::Size;
+
+use crate::input::InputQueue;
+use crate::{EventStream, Host, InputBuffer, TerminalHandle, TerminalInput, TerminalQueries};
+
+const DEFAULT_SCROLLBACK: usize = 1000;
+
+/// A [`Host`] that emulates a terminal in memory.
+///
+/// Output is parsed into a grid of cells, including the cursor, scrollback, the alternate screen
+/// and scroll regions. This makes it possible to check what the browser would show without
+/// running one.
+///
+/// Input works the same as with [`MemoryTerminal`](crate::MemoryTerminal).
+///
+/// This is a cheaply cloneable reference. All clones refer to the same terminal.
+#[derive(Clone)]
+pub struct EmulatedTerminal {
+    parser: Rc<RefCell<vt100::Parser>>,
+    input: InputQueue,
+}
+
+impl EmulatedTerminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            parser: Rc::new(RefCell::new(vt100::Parser::new(
+                rows,
+                cols,
+                DEFAULT_SCROLLBACK,
+            ))),
+            input: InputQueue::new(InputBuffer::Unbounded),
+        }
+    }
+
+    /// Sets how much input is buffered before it's read. Defaults to
+    /// [`InputBuffer::Unbounded`].
+    pub fn with_input_buffer(mut self, input_buffer: InputBuffer) -> Self {
+        self.input = InputQueue::new(input_buffer);
+        self
+    }
+
+    /// Sets the number of lines kept in the scrollback buffer.
+    pub fn with_scrollback(self, lines: usize) -> Self {
+        let (rows, cols) = self.screen().size();
+        *self.parser.borrow_mut() = vt100::Parser::new(rows, cols, lines);
+        self
+    }
+
+    /// Creates a new output handle that writes to this terminal.
+    pub fn handle(&self) -> TerminalHandle<Self> {
+        TerminalHandle::new(self.clone())
+    }
+
+    /// Creates a stream of input events from this terminal.
+    ///
+    /// Only one stream can read from the terminal at a time. See [`EventStream`].
+    pub fn event_stream(&self) -> EventStream<Self> {
+        EventStream::new(self.clone())
+    }
+
+    /// Creates a handle for querying this terminal.
+    ///
+    /// The emulator doesn't answer queries itself. Send the replies with
+    /// [`send_input`](Self::send_input) after the query was written.
+    pub fn queries(&self) -> TerminalQueries<Self> {
+        TerminalQueries::new(self.clone(), self.input.queries())
+    }
+
+    /// Returns the current state of the screen.
+    pub fn screen(&self) -> Ref<'_, vt100::Screen> {
+        Ref::map(self.parser.borrow(), |p| p.screen())
+    }
+
+    /// Scrolls the visible screen back by the given number of lines into the scrollback
+    /// buffer. Use `0` to show the live screen again.
+    pub fn set_scrollback(&self, lines: usize) {
+        self.parser.borrow_mut().set_scrollback(lines);
+    }
+
+    /// Returns the visible text on the screen, without formatting.
+    pub fn contents(&self) -> String {
+        self.screen().contents()
+    }
+
+    /// Changes the size of the terminal and sends a resize event.
+    pub fn resize(&self, cols: u16, rows: u16) {
+        self.parser.borrow_mut().set_size(rows, cols);
+        self.input.push(TerminalInput::Resize(cols, rows));
+    }
+
+    /// Sends input as if it was typed into the terminal.
+    pub fn send_input(&self, data: impl AsRef<[u8]>) {
+        self.input.push(TerminalInput::Data(data.as_ref().to_vec()));
+    }
+
+    /// Sends a focus change as if the terminal gained or lost focus.
+    pub fn set_focus(&self, focused: bool) {
+        self.input.push(TerminalInput::Focus(focused));
+    }
+
+    /// Returns the total number of inputs dropped because the input buffer was full.
+    pub fn dropped_input_count(&self) -> usize {
+        self.input.dropped()
+    }
+
+    /// Ends any event streams once the remaining input is read.
+    pub fn close(&self) {
+        self.input.close();
+    }
+}
+
+impl Host for EmulatedTerminal {
+    fn write(&self, data: &str) -> io::Result<()> {
+        self.parser.borrow_mut().process(data.as_bytes());
+        Ok(())
+    }
+
+    fn window_size(&self) -> io::Result<WindowSize> {
+        let (rows, cols) = self.screen().size();
+        Ok(WindowSize {
+            columns_rows: Size::new(cols, rows),
+            pixels: Size::default(),
+        })
+    }
+
+    fn cursor_position(&self) -> io::Result<(u16, u16)> {
+        let (row, col) = self.screen().cursor_position();
+        Ok((col, row))
+    }
+
+    fn poll_input(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>> {
+        self.input.poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::{StreamExt, join};
+
+    use super::EmulatedTerminal;
+    use crate::{Host, InputBuffer};
+
+    fn rows(terminal: &EmulatedTerminal) -> Vec<String> {
+        let (_, cols) = terminal.screen().size();
+        terminal.screen().rows(0, cols).collect()
+    }
+
+    #[test]
+    fn tracks_the_cursor() {
+        let terminal = EmulatedTerminal::new(10, 3);
+        terminal.write("ab\r\nc").unwrap();
+        assert_eq!(terminal.cursor_position().unwrap(), (1, 1));
+        terminal.write("\x1B[3;5H").unwrap();
+        assert_eq!(terminal.cursor_position().unwrap(), (4, 2));
+    }
+
+    #[test]
+    fn keeps_scrollback() {
+        let terminal = EmulatedTerminal::new(10, 2).with_scrollback(10);
+        terminal.write("one\r\ntwo\r\nthree").unwrap();
+        assert_eq!(rows(&terminal), ["two", "three"]);
+        terminal.set_scrollback(1);
+        assert_eq!(rows(&terminal), ["one", "two"]);
+        terminal.set_scrollback(0);
+        assert_eq!(rows(&terminal), ["two", "three"]);
+    }
+
+    #[test]
+    fn restores_the_screen_after_the_alternate_screen() {
+        let terminal = EmulatedTerminal::new(10, 2);
+        terminal.write("main").unwrap();
+        terminal.write("\x1B[?1049h").unwrap();
+        assert!(terminal.screen().alternate_screen());
+        assert_eq!(terminal.contents(), "");
+        terminal.write("alt").unwrap();
+        terminal.write("\x1B[?1049l").unwrap();
+        assert!(!terminal.screen().alternate_screen());
+        assert_eq!(terminal.contents(), "main");
+    }
+
+    #[test]
+    fn scrolls_within_the_scroll_region() {
+        let terminal = EmulatedTerminal::new(10, 4);
+        terminal.write("a\r\nb\r\nc\r\nd").unwrap();
+        // Scroll rows 2 and 3 up by one line
+        terminal.write("\x1B[2;3r\x1B[3;1H\n\x1B[r").unwrap();
+        assert_eq!(rows(&terminal), ["a", "c", "", "d"]);
+    }
+
+    #[test]
+    fn resizes_the_screen() {
+        let terminal = EmulatedTerminal::new(10, 2);
+        terminal.resize(20, 5);
+        assert_eq!(terminal.size().unwrap(), (20, 5));
+        assert_eq!(terminal.screen().size(), (5, 20));
+    }
+
+    #[test]
+    fn answers_queries_with_sent_input() {
+        let terminal = EmulatedTerminal::new(10, 2);
+        let queries = terminal.queries();
+        let (position, ()) = block_on(async {
+            join!(queries.cursor_position(), async {
+                terminal.send_input("\x1B[2;4R");
+            })
+        });
+        assert_eq!(position.unwrap(), (3, 1));
+    }
+
+    #[test]
+    fn reports_dropped_input() {
+        let terminal = EmulatedTerminal::new(10, 2).with_input_buffer(InputBuffer::Bounded(1));
+        terminal.send_input("a");
+        terminal.send_input("b");
+        terminal.close();
+        assert_eq!(terminal.dropped_input_count(), 1);
+        let events: Vec<_> = block_on(terminal.event_stream().collect());
+        assert!(events[0].is_ok());
+        assert!(events[1].is_err());
+    }
+}
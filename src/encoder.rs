@@ -0,0 +1,751 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use ratatui::buffer::Cell;
+use ratatui::layout::{Position, Size};
+use ratatui::style::{Color, Modifier};
+use ratatui::text::Span;
+
+use crate::ansi;
+use crate::link::Link;
+
+/// The colors and attributes that the terminal draws text with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Pen {
+    fg: Color,
+    bg: Color,
+    #[cfg(feature = "underline-color")]
+    underline_color: Color,
+    modifier: Modifier,
+}
+
+impl Pen {
+    const RESET: Self = Self {
+        fg: Color::Reset,
+        bg: Color::Reset,
+        #[cfg(feature = "underline-color")]
+        underline_color: Color::Reset,
+        modifier: Modifier::empty(),
+    };
+
+    fn of(cell: &Cell) -> Self {
+        Self {
+            fg: cell.fg,
+            bg: cell.bg,
+            #[cfg(feature = "underline-color")]
+            underline_color: cell.underline_color,
+            modifier: cell.modifier,
+        }
+    }
+
+    /// Returns the SGR parameters that set this pen after a full reset.
+    fn params(self) -> Vec<u16> {
+        // `CSI m` on its own resets everything
+        if self == Self::RESET {
+            return Vec::new();
+        }
+        let mut params = vec![0];
+        ansi::push_modifier_changes(&mut params, Modifier::empty(), self.modifier);
+        if self.fg != Color::Reset {
+            ansi::push_fg(&mut params, self.fg);
+        }
+        if self.bg != Color::Reset {
+            ansi::push_bg(&mut params, self.bg);
+        }
+        #[cfg(feature = "underline-color")]
+        if self.underline_color != Color::Reset {
+            ansi::push_underline_color(&mut params, self.underline_color);
+        }
+        params
+    }
+
+    /// Returns the SGR parameters that switch from this pen to `to`.
+    fn changes(self, to: Self) -> Vec<u16> {
+        let mut params = Vec::new();
+        ansi::push_modifier_changes(&mut params, self.modifier, to.modifier);
+        if self.fg != to.fg {
+            ansi::push_fg(&mut params, to.fg);
+        }
+        if self.bg != to.bg {
+            ansi::push_bg(&mut params, to.bg);
+        }
+        #[cfg(feature = "underline-color")]
+        if self.underline_color != to.underline_color {
+            ansi::push_underline_color(&mut params, to.underline_color);
+        }
+        params
+    }
+}
+
+/// Encodes frames with as few bytes as it can.
+///
+/// The encoder remembers the pen, the cursor and the cells it has drawn, so it only sends
+/// attribute changes, moves the cursor with the shortest sequence (or by writing the cells in
+/// between again when they're unchanged), and uses erase and repeat sequences for runs of equal
+/// cells when that's shorter.
+///
+/// Cells inside a link's area are wrapped in OSC 8 hyperlinks. When the links change, the cells
+/// whose link changed are drawn again from the last frames, even where the encoder no longer
+/// knows what's on the screen.
+///
+/// Each frame starts from an unknown cursor position, since other output may have moved it.
+/// The cells are only forgotten when the screen is cleared, scrolled or written to directly.
+pub(crate) struct FrameEncoder {
+    /// The cursor position. Either coordinate is `None` when it isn't known.
+    cursor: (Option<u16>, Option<u16>),
+    pen: Pen,
+    /// The cells on the screen, row by row, or `None` where they aren't known.
+    screen: Vec<Option<Cell>>,
+    /// The cells last drawn at each position, row by row, or `None` where nothing was drawn.
+    /// Unlike `screen`, these are kept when the screen is invalidated, since ratatui assumes
+    /// they're still there and only draws the cells that change.
+    drawn: Vec<Option<Cell>>,
+    size: Size,
+    /// Whether the terminal understands REP (`CSI n b`).
+    repeat: bool,
+    /// The links of the current frame.
+    links: Vec<Link>,
+    /// The URL of the hyperlink that's open, which written cells link to.
+    open_link: Option<String>,
+}
+
+impl FrameEncoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            cursor: (None, None),
+            pen: Pen::RESET,
+            screen: Vec::new(),
+            drawn: Vec::new(),
+            size: Size::default(),
+            repeat: false,
+            links: Vec::new(),
+            open_link: None,
+        }
+    }
+
+    /// Forgets everything on the screen.
+    pub(crate) fn invalidate(&mut self) {
+        self.screen.fill(None);
+        self.cursor = (None, None);
+    }
+
+    /// Records that the whole screen was erased.
+    pub(crate) fn cleared(&mut self) {
+        // The pen is always reset between frames, so the screen is blank
+        self.screen.fill(Some(Cell::default()));
+        self.drawn.fill(Some(Cell::default()));
+    }
+
+    /// Records that an unknown part of the screen was erased.
+    pub(crate) fn partially_cleared(&mut self) {
+        self.invalidate();
+        self.drawn.fill(None);
+    }
+
+    /// Records that the rows in `region` moved up by `lines` rows, or down if `up` is false.
+    #[cfg(feature = "scrolling-regions")]
+    pub(crate) fn scrolled(&mut self, region: std::ops::Range<u16>, lines: u16, up: bool) {
+        self.invalidate();
+        let width = usize::from(self.size.width);
+        let end = region.end.min(self.size.height);
+        if region.start >= end {
+            return;
+        }
+        let rows = &mut self.drawn[usize::from(region.start) * width..usize::from(end) * width];
+        // The rows that scroll into the region are blank
+        let shift = usize::from(lines.min(end - region.start)) * width;
+        if up {
+            rows.rotate_left(shift);
+            let len = rows.len();
+            rows[len - shift..].fill(Some(Cell::default()));
+        } else {
+            rows.rotate_right(shift);
+            rows[..shift].fill(Some(Cell::default()));
+        }
+    }
+
+    /// Writes the changed cells of a frame with the frame's links, and resets the pen at the end.
+    pub(crate) fn encode<'a>(
+        &mut self,
+        w: &mut impl Write,
+        size: Size,
+        repeat: bool,
+        links: Vec<Link>,
+        content: impl Iterator<Item = (u16, u16, &'a Cell)>,
+    ) -> io::Result<()> {
+        if size != self.size {
+            self.size = size;
+            self.screen = vec![None; usize::from(size.width) * usize::from(size.height)];
+            self.drawn = self.screen.clone();
+        }
+        self.repeat = repeat;
+        self.cursor = (None, None);
+
+        let mut content: Vec<_> = content
+            .filter(|&(x, y, _)| x < size.width && y < size.height)
+            .collect();
+        let relinked = if links != self.links {
+            let relinked = self.relinked_cells(&links, &content);
+            self.links = links;
+            relinked
+        } else {
+            Vec::new()
+        };
+        if !relinked.is_empty() {
+            content.extend(relinked.iter().map(|(x, y, cell)| (*x, *y, cell)));
+            content.sort_by_key(|&(x, y, _)| (y, x));
+        }
+
+        let mut i = 0;
+        while i < content.len() {
+            let (x, y, cell) = content[i];
+            let link = self.link_at(x, y).map(str::to_string);
+            // The number of following cells that are the same as this one
+            let run = content[i + 1..]
+                .iter()
+                .zip(1..)
+                .take_while(|&(&(rx, ry, other), n)| {
+                    ry == y
+                        && rx == x + n
+                        && other == cell
+                        && self.link_at(rx, ry) == link.as_deref()
+                })
+                .count() as u16;
+            self.move_to(w, x, y)?;
+            self.set_pen(w, Pen::of(cell))?;
+            self.set_link(w, link)?;
+            self.write_run(w, x, y, cell, run + 1)?;
+            i += usize::from(run) + 1;
+        }
+        self.set_link(w, None)?;
+        self.set_pen(w, Pen::RESET)
+    }
+
+    /// Returns the drawn cells that aren't in `content` and whose link differs between the
+    /// current links and `links`.
+    fn relinked_cells(
+        &self,
+        links: &[Link],
+        content: &[(u16, u16, &Cell)],
+    ) -> Vec<(u16, u16, Cell)> {
+        // Skip the cells the frame draws anyway, including those covered by wide characters
+        let mut seen: HashSet<_> = content
+            .iter()
+            .flat_map(|&(x, y, cell)| {
+                let width = Span::raw(cell.symbol()).width().max(1) as u16;
+                (x..x.saturating_add(width)).map(move |x| (x, y))
+            })
+            .collect();
+        let mut relinked = Vec::new();
+        for area in self.links.iter().chain(links).map(|link| link.area) {
+            for Position { x, y } in area.positions() {
+                if self.link_at(x, y) == link_at(links, x, y) || !seen.insert((x, y)) {
+                    continue;
+                }
+                let drawn = self.index(x, y).and_then(|i| self.drawn[i].as_ref());
+                if let Some(cell) = drawn {
+                    relinked.push((x, y, cell.clone()));
+                }
+            }
+        }
+        relinked
+    }
+
+    fn link_at(&self, x: u16, y: u16) -> Option<&str> {
+        link_at(&self.links, x, y)
+    }
+
+    /// Opens the hyperlink to `url`, or closes the open one if it's `None`.
+    fn set_link(&mut self, w: &mut impl Write, url: Option<String>) -> io::Result<()> {
+        if url == self.open_link {
+            return Ok(());
+        }
+        write!(w, "\x1B]8;;{}\x1B\\", url.as_deref().unwrap_or_default())?;
+        self.open_link = url;
+        Ok(())
+    }
+
+    /// Writes `count` copies of `cell` starting at the cursor, which is at `(x, y)`.
+    fn write_run(
+        &mut self,
+        w: &mut impl Write,
+        x: u16,
+        y: u16,
+        cell: &Cell,
+        count: u16,
+    ) -> io::Result<()> {
+        let symbol = cell.symbol();
+        if !is_narrow(symbol) {
+            for i in 0..count {
+                if i > 0 {
+                    // The cursor position is unknown after each symbol
+                    self.move_to(w, x + i, y)?;
+                }
+                w.write_all(symbol.as_bytes())?;
+                // Only the drawn cell is kept, since the terminal may disagree about its width
+                let width = Span::raw(symbol).width().max(1) as u16;
+                self.forget_cells(x + i, y, width);
+                if let Some(index) = self.index(x + i, y) {
+                    self.drawn[index] = Some(cell.clone());
+                }
+                self.cursor = (None, None);
+            }
+            return Ok(());
+        }
+
+        let end = x + count;
+        // Erased cells only take the background color, so attributes and links must be off
+        let blank = symbol == " " && cell.modifier.is_empty() && self.open_link.is_none();
+        if blank && end == self.size.width {
+            // Erase to the end of the line, which leaves the cursor where it is
+            w.write_all(b"\x1B[K")?;
+            self.remember_cells(x, y, count, cell);
+            return Ok(());
+        }
+
+        let written = usize::from(count) * symbol.len();
+        let repeated = if self.repeat && count > 1 && self.open_link.is_none() {
+            symbol.len() + 3 + ansi::digits(count - 1)
+        } else {
+            usize::MAX
+        };
+        // Erasing doesn't move the cursor, so count moving past the erased cells
+        let erased = if blank && count > 1 {
+            3 + ansi::digits(count) + 3 + ansi::digits(count)
+        } else {
+            usize::MAX
+        };
+
+        if erased < written && erased < repeated {
+            write!(w, "\x1B[{count}X")?;
+            self.remember_cells(x, y, count, cell);
+            return Ok(());
+        }
+        if repeated < written {
+            w.write_all(symbol.as_bytes())?;
+            write!(w, "\x1B[{}b", count - 1)?;
+        } else {
+            for _ in 0..count {
+                w.write_all(symbol.as_bytes())?;
+            }
+        }
+        self.remember_cells(x, y, count, cell);
+        // Writing the last column leaves the cursor there until the next character wraps it
+        self.cursor = if end < self.size.width {
+            (Some(end), Some(y))
+        } else {
+            (None, None)
+        };
+        Ok(())
+    }
+
+    /// Moves the cursor to `(x, y)` with the shortest sequence.
+    fn move_to(&mut self, w: &mut impl Write, x: u16, y: u16) -> io::Result<()> {
+        if self.cursor == (Some(x), Some(y)) {
+            return Ok(());
+        }
+        let mut best = match (x, y) {
+            (0, 0) => "\x1B[H".to_string(),
+            (0, y) => format!("\x1B[{}H", y + 1),
+            (x, y) => format!("\x1B[{};{}H", y + 1, x + 1),
+        };
+
+        if let Some(cy) = self.cursor.1 {
+            let vertical = match cy.cmp(&y) {
+                std::cmp::Ordering::Equal => String::new(),
+                std::cmp::Ordering::Less => relative(y - cy, 'B'),
+                std::cmp::Ordering::Greater => relative(cy - y, 'A'),
+            };
+            let mut horizontal = vec![format!("\x1B[{}G", x + 1)];
+            if x == 0 {
+                horizontal.push("\r".to_string());
+            } else {
+                horizontal.push(format!("\r{}", relative(x, 'C')));
+            }
+            if let Some(cx) = self.cursor.0 {
+                match cx.cmp(&x) {
+                    std::cmp::Ordering::Equal => horizontal.push(String::new()),
+                    std::cmp::Ordering::Less => {
+                        horizontal.push(relative(x - cx, 'C'));
+                        if cy == y {
+                            horizontal.extend(self.write_through(cx, x, y));
+                        }
+                    }
+                    std::cmp::Ordering::Greater => {
+                        horizontal.push(relative(cx - x, 'D'));
+                        horizontal.push("\x08".repeat(usize::from(cx - x)));
+                    }
+                }
+            }
+            let horizontal = horizontal.into_iter().min_by_key(String::len).unwrap();
+            if vertical.len() + horizontal.len() < best.len() {
+                best = vertical + &horizontal;
+            }
+        }
+
+        w.write_all(best.as_bytes())?;
+        self.cursor = (Some(x), Some(y));
+        Ok(())
+    }
+
+    /// Returns the cells from `from` up to `to` in row `y` if they can be written again to move
+    /// the cursor past them without changing anything.
+    fn write_through(&self, from: u16, to: u16, y: u16) -> Option<String> {
+        let mut text = String::new();
+        for x in from..to {
+            let cell = self.cell(x, y)?.as_ref()?;
+            // Writing the cell again also gives it the open link
+            if Pen::of(cell) != self.pen
+                || !is_narrow(cell.symbol())
+                || self.link_at(x, y) != self.open_link.as_deref()
+            {
+                return None;
+            }
+            text.push_str(cell.symbol());
+        }
+        Some(text)
+    }
+
+    fn set_pen(&mut self, w: &mut impl Write, pen: Pen) -> io::Result<()> {
+        if pen == self.pen {
+            return Ok(());
+        }
+        let changes = self.pen.changes(pen);
+        let params = pen.params();
+        if ansi::sgr_len(&params) < ansi::sgr_len(&changes) {
+            ansi::sgr(w, &params)?;
+        } else {
+            ansi::sgr(w, &changes)?;
+        }
+        self.pen = pen;
+        Ok(())
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        (x < self.size.width && y < self.size.height)
+            .then(|| usize::from(y) * usize::from(self.size.width) + usize::from(x))
+    }
+
+    fn cell(&self, x: u16, y: u16) -> Option<&Option<Cell>> {
+        self.screen.get(self.index(x, y)?)
+    }
+
+    fn remember_cells(&mut self, x: u16, y: u16, count: u16, cell: &Cell) {
+        for x in x..x.saturating_add(count) {
+            if let Some(i) = self.index(x, y) {
+                self.screen[i] = Some(cell.clone());
+                self.drawn[i] = Some(cell.clone());
+            }
+        }
+    }
+
+    fn forget_cells(&mut self, x: u16, y: u16, count: u16) {
+        for x in x..x.saturating_add(count) {
+            if let Some(i) = self.index(x, y) {
+                self.screen[i] = None;
+                self.drawn[i] = None;
+            }
+        }
+    }
+}
+
+/// Returns the URL of the link at `(x, y)`. Later links take precedence.
+fn link_at(links: &[Link], x: u16, y: u16) -> Option<&str> {
+    links
+        .iter()
+        .rev()
+        .find(|link| link.area.contains((x, y).into()))
+        .map(|link| link.url.as_str())
+}
+
+/// Returns the sequence that moves the cursor `n` cells in the direction of `command`.
+fn relative(n: u16, command: char) -> String {
+    if n == 1 {
+        format!("\x1B[{command}")
+    } else {
+        format!("\x1B[{n}{command}")
+    }
+}
+
+/// Returns whether `symbol` is a single character that takes up one cell, so the cursor
+/// position after writing it is certain.
+fn is_narrow(symbol: &str) -> bool {
+    let mut chars = symbol.chars();
+    chars.next().is_some_and(|c| !c.is_control())
+        && chars.next().is_none()
+        && Span::raw(symbol).width() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::buffer::Cell;
+    use ratatui::layout::{Rect, Size};
+    use ratatui::style::{Color, Modifier, Style};
+
+    use super::FrameEncoder;
+    use crate::link::Link;
+
+    fn link(x: u16, width: u16) -> Link {
+        Link {
+            area: Rect::new(x, 0, width, 1),
+            url: "https://x".to_string(),
+        }
+    }
+
+    fn cell(symbol: &str, style: Style) -> Cell {
+        let mut cell = Cell::default();
+        cell.set_symbol(symbol).set_style(style);
+        cell
+    }
+
+    /// Returns the cells for `text` starting at column `x` of the first row.
+    fn text(x: u16, text: &str, style: Style) -> Vec<(u16, u16, Cell)> {
+        text.chars()
+            .zip(x..)
+            .map(|(c, x)| (x, 0, cell(&c.to_string(), style)))
+            .collect()
+    }
+
+    fn encode(
+        encoder: &mut FrameEncoder,
+        width: u16,
+        repeat: bool,
+        cells: &[(u16, u16, Cell)],
+    ) -> String {
+        encode_with_links(encoder, width, repeat, Vec::new(), cells)
+    }
+
+    fn encode_with_links(
+        encoder: &mut FrameEncoder,
+        width: u16,
+        repeat: bool,
+        links: Vec<Link>,
+        cells: &[(u16, u16, Cell)],
+    ) -> String {
+        let mut output = Vec::new();
+        let content = cells.iter().map(|(x, y, cell)| (*x, *y, cell));
+        encoder
+            .encode(&mut output, Size::new(width, 2), repeat, links, content)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn sends_only_the_pen_changes() {
+        let bold = Style::new()
+            .fg(Color::LightRed)
+            .add_modifier(Modifier::BOLD);
+        let dim = Style::new().fg(Color::LightRed).add_modifier(Modifier::DIM);
+        let cells = [text(0, "a", bold), text(1, "b", dim)].concat();
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 10, false, &cells),
+            "\x1B[H\x1B[1;91ma\x1B[22;2mb\x1B[m"
+        );
+    }
+
+    #[test]
+    fn resets_the_pen_when_that_is_shorter() {
+        let styled = Style::new().add_modifier(Modifier::BOLD | Modifier::ITALIC);
+        let cells = [text(0, "a", styled), text(1, "b", Style::new())].concat();
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 10, false, &cells),
+            "\x1B[H\x1B[1;3ma\x1B[mb"
+        );
+    }
+
+    #[test]
+    fn erases_to_the_end_of_the_line_only_at_the_right_edge() {
+        let plain = Style::new();
+        let cells = text(0, "a     ", plain);
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 6, false, &cells),
+            "\x1B[Ha\x1B[K"
+        );
+        let cells = text(0, "a    b", plain);
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 6, false, &cells),
+            "\x1B[Ha    b"
+        );
+    }
+
+    #[test]
+    fn erases_long_runs_of_blank_cells() {
+        let cells = text(0, &format!("{}x", " ".repeat(12)), Style::new());
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, false, &cells),
+            "\x1B[H\x1B[12X\x1B[13Gx"
+        );
+        // Short runs are cheaper to write
+        let cells = text(0, &format!("{}x", " ".repeat(10)), Style::new());
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, false, &cells),
+            format!("\x1B[H{}x", " ".repeat(10))
+        );
+    }
+
+    #[test]
+    fn writes_blank_cells_with_attributes() {
+        // Erasing would drop the underline
+        let underlined = Style::new().add_modifier(Modifier::UNDERLINED);
+        let cells = text(0, &" ".repeat(12), underlined);
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, false, &cells),
+            format!("\x1B[H\x1B[4m{}\x1B[m", " ".repeat(12))
+        );
+        // The background color is kept by erasing
+        let cells = text(0, &" ".repeat(12), Style::new().bg(Color::Blue));
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, false, &cells),
+            "\x1B[H\x1B[44m\x1B[12X\x1B[m"
+        );
+    }
+
+    #[test]
+    fn repeats_runs_only_when_the_terminal_supports_it() {
+        let cells = text(0, &"a".repeat(10), Style::new());
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, true, &cells),
+            "\x1B[Ha\x1B[9b"
+        );
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, false, &cells),
+            format!("\x1B[H{}", "a".repeat(10))
+        );
+        // Short runs are cheaper to write
+        let cells = text(0, "aaa", Style::new());
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 20, true, &cells),
+            "\x1B[Haaa"
+        );
+    }
+
+    #[test]
+    fn forgets_the_cursor_after_wide_characters() {
+        let cells = [
+            (0, 0, cell("中", Style::new())),
+            (2, 0, cell("b", Style::new())),
+        ];
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 10, false, &cells),
+            "\x1B[H中\x1B[1;3Hb"
+        );
+        let cells = text(0, "ab", Style::new());
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 10, false, &cells),
+            "\x1B[Hab"
+        );
+    }
+
+    #[test]
+    fn forgets_the_cursor_after_the_last_column() {
+        let mut encoder = FrameEncoder::new();
+        encode(&mut encoder, 3, false, &text(1, "a", Style::new()));
+        assert_eq!(encoder.cursor, (Some(2), Some(0)));
+        encode(&mut encoder, 3, false, &text(2, "a", Style::new()));
+        assert_eq!(encoder.cursor, (None, None));
+
+        let cells = [text(2, "a", Style::new()), vec![(2, 1, Cell::new("b"))]].concat();
+        assert_eq!(
+            encode(&mut FrameEncoder::new(), 3, false, &cells),
+            "\x1B[1;3Ha\x1B[2;3Hb"
+        );
+    }
+
+    #[test]
+    fn writes_through_remembered_cells_until_invalidated() {
+        let mut encoder = FrameEncoder::new();
+        encode(&mut encoder, 10, false, &text(0, "abc", Style::new()));
+        let cells = [text(0, "x", Style::new()), text(2, "y", Style::new())].concat();
+        assert_eq!(encode(&mut encoder, 10, false, &cells), "\x1B[Hxby");
+
+        encoder.invalidate();
+        assert_eq!(encode(&mut encoder, 10, false, &cells), "\x1B[Hx\x1B[Cy");
+    }
+
+    #[test]
+    fn wraps_linked_cells_in_hyperlinks() {
+        let cells = text(0, "abcd", Style::new());
+        assert_eq!(
+            encode_with_links(
+                &mut FrameEncoder::new(),
+                10,
+                false,
+                vec![link(1, 2)],
+                &cells
+            ),
+            "\x1B[Ha\x1B]8;;https://x\x1B\\bc\x1B]8;;\x1B\\d"
+        );
+    }
+
+    #[test]
+    fn does_not_repeat_inside_links() {
+        let cells = text(0, &"a".repeat(10), Style::new());
+        assert_eq!(
+            encode_with_links(
+                &mut FrameEncoder::new(),
+                20,
+                true,
+                vec![link(0, 10)],
+                &cells
+            ),
+            format!(
+                "\x1B[H\x1B]8;;https://x\x1B\\{}\x1B]8;;\x1B\\",
+                "a".repeat(10)
+            )
+        );
+    }
+
+    #[test]
+    fn redraws_cells_whose_link_changed() {
+        let mut encoder = FrameEncoder::new();
+        encode(&mut encoder, 10, false, &text(0, "abc", Style::new()));
+        assert_eq!(
+            encode_with_links(&mut encoder, 10, false, vec![link(0, 2)], &[]),
+            "\x1B[H\x1B]8;;https://x\x1B\\ab\x1B]8;;\x1B\\"
+        );
+        assert_eq!(encode(&mut encoder, 10, false, &[]), "\x1B[Hab");
+        assert_eq!(encode(&mut encoder, 10, false, &[]), "");
+    }
+
+    #[test]
+    fn redraws_wide_characters_whose_link_changed() {
+        let mut encoder = FrameEncoder::new();
+        encode(&mut encoder, 10, false, &[(0, 0, cell("界", Style::new()))]);
+        assert_eq!(
+            encode_with_links(&mut encoder, 10, false, vec![link(0, 2)], &[]),
+            "\x1B[H\x1B]8;;https://x\x1B\\界\x1B]8;;\x1B\\"
+        );
+    }
+
+    #[test]
+    fn redraws_cells_whose_link_changed_after_invalidating() {
+        let mut encoder = FrameEncoder::new();
+        encode(&mut encoder, 10, false, &text(0, "ab", Style::new()));
+        encoder.invalidate();
+        assert_eq!(
+            encode_with_links(&mut encoder, 10, false, vec![link(0, 2)], &[]),
+            "\x1B[H\x1B]8;;https://x\x1B\\ab\x1B]8;;\x1B\\"
+        );
+
+        // Nothing is known to be drawn after part of the screen was erased
+        encoder.partially_cleared();
+        assert_eq!(encode(&mut encoder, 10, false, &[]), "");
+    }
+
+    #[test]
+    #[cfg(feature = "scrolling-regions")]
+    fn redraws_scrolled_cells_whose_link_changed() {
+        let mut encoder = FrameEncoder::new();
+        let cells = [text(0, "ab", Style::new()), vec![(0, 1, Cell::new("c"))]].concat();
+        encode(&mut encoder, 2, false, &cells);
+        encoder.scrolled(0..2, 1, true);
+        assert_eq!(
+            encode_with_links(&mut encoder, 2, false, vec![link(0, 2)], &[]),
+            "\x1B[H\x1B]8;;https://x\x1B\\c\x1B]8;;\x1B\\"
+        );
+
+        // The row scrolled in at the top is blank
+        encoder.scrolled(0..2, 1, false);
+        assert_eq!(encode(&mut encoder, 2, false, &[]), "\x1B[H\x1B[K");
+    }
+}
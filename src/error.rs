@@ -0,0 +1,57 @@
+use std::{fmt, io};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{JsCast, JsValue};
+
+/// Errors returned when interacting with a terminal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The terminal was disposed.
+    Disposed,
+    /// The terminal sent input that wasn't a string.
+    NonStringPayload,
+    /// A JavaScript exception was thrown. Contains the exception's message.
+    Js(String),
+    /// The given number of inputs were dropped because the input buffer was full.
+    InputDropped(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disposed => write!(f, "terminal was disposed"),
+            Self::NonStringPayload => write!(f, "received non-string input from xterm.js"),
+            Self::Js(message) => write!(f, "JavaScript exception: {message}"),
+            Self::InputDropped(count) => write!(
+                f,
+                "{count} input events were dropped because the input buffer was full"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(target_arch = "wasm32")]
+impl From<JsValue> for Error {
+    fn from(value: JsValue) -> Self {
+        let message = match value.dyn_ref::<js_sys::Error>() {
+            Some(e) => e.message().into(),
+            None => value.as_string().unwrap_or_else(|| format!("{value:?}")),
+        };
+        Self::Js(message)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<Error> for JsValue {
+    fn from(value: Error) -> Self {
+        js_sys::Error::new(&value.to_string()).into()
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        io::Error::other(value)
+    }
+}
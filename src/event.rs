@@ -1,46 +1,321 @@
+use std::collections::VecDeque;
 use std::io;
-use std::task::{Poll, ready};
+use std::task::Poll;
 
 use futures::Stream;
 use terminput::Event;
-use terminput_crossterm::to_crossterm;
 
-use crate::poll_next_event;
+use crate::{Error, Host, TerminalInput};
 
-#[derive(Default)]
-pub struct EventStream {}
+const ESC: u8 = b'\x1B';
+const PASTE_START: &[u8] = b"\x1B[200~";
+const PASTE_END: &[u8] = b"\x1B[201~";
+
+/// A stream of input events from a [`Host`].
+///
+/// Events are decoded into [`terminput`] events, which can be converted to the event types of
+/// other terminal libraries.
+///
+/// If bracketed paste is enabled (`CSI ?2004h`), pasted text is returned as a single
+/// [`Event::Paste`].
+///
+/// Focus changes are always reported. If focus reporting is enabled (`CSI ?1004h`), xterm's own
+/// focus reports are used instead of the browser's focus events.
+///
+/// Read each terminal through a single stream. All streams of a terminal take input from the
+/// same queue, and only the stream that was polled last is woken when input arrives, so a
+/// second stream would leave the first one waiting.
+pub struct EventStream<H> {
+    terminal: H,
+    parser: Parser,
+}
 
-impl EventStream {
-    pub fn new() -> Self {
-        Self {}
+impl<H> EventStream<H> {
+    pub fn new(terminal: H) -> Self {
+        Self {
+            terminal,
+            parser: Parser::default(),
+        }
     }
 }
 
-impl Stream for EventStream {
-    type Item = io::Result<crossterm::event::Event>;
+impl<H: Host + Unpin> Stream for EventStream<H> {
+    type Item = io::Result<Event>;
 
     fn poll_next(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
         loop {
-            if let Some(event) = ready!(poll_next_event(cx)) {
-                match Event::parse_from(event.as_bytes()) {
-                    Ok(Some(e)) => {
-                        if let Ok(e) = to_crossterm(e) {
-                            return Poll::Ready(Some(Ok(e)));
-                        }
-                    }
-                    Err(e) => {
-                        return Poll::Ready(Some(Err(e)));
-                    }
-                    _ => {
-                        continue;
-                    }
-                }
-            } else {
-                return Poll::Pending;
+            if let Some(event) = this.parser.pop_event() {
+                return Poll::Ready(Some(event));
             }
+            let input = match this.terminal.poll_input(cx) {
+                Poll::Ready(Some(input)) => input,
+                // Nothing follows a trailing escape for now, so it's the escape key
+                Poll::Ready(None) | Poll::Pending if this.parser.finish_escape() => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            match input {
+                TerminalInput::Data(data) => {
+                    this.parser.advance(&data);
+                }
+                TerminalInput::Resize(cols, rows) => {
+                    return Poll::Ready(Some(Ok(Event::Resize {
+                        cols: cols.into(),
+                        rows: rows.into(),
+                    })));
+                }
+                TerminalInput::Focus(true) => {
+                    return Poll::Ready(Some(Ok(Event::FocusGained)));
+                }
+                TerminalInput::Focus(false) => {
+                    return Poll::Ready(Some(Ok(Event::FocusLost)));
+                }
+                TerminalInput::Error(e) => {
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                TerminalInput::Dropped(count) => {
+                    return Poll::Ready(Some(Err(Error::InputDropped(count).into())));
+                }
+            }
+        }
+    }
+}
+
+/// Incremental decoder for terminal input.
+///
+/// Input may contain any number of events and may end partway through an escape sequence, so
+/// incomplete sequences are kept until the rest of the sequence arrives.
+#[derive(Default)]
+struct Parser {
+    buffer: Vec<u8>,
+    /// Text received so far while inside a bracketed paste.
+    paste: Option<Vec<u8>>,
+    events: VecDeque<io::Result<Event>>,
+}
+
+impl Parser {
+    fn advance(&mut self, data: &[u8]) {
+        for byte in data {
+            if let Some(paste) = &mut self.paste {
+                paste.push(*byte);
+                if paste.ends_with(PASTE_END) {
+                    paste.truncate(paste.len() - PASTE_END.len());
+                    let text = String::from_utf8_lossy(paste).into_owned();
+                    self.events.push_back(Ok(Event::Paste(text)));
+                    self.paste = None;
+                }
+                continue;
+            }
+
+            self.buffer.push(*byte);
+            if self.buffer == PASTE_START {
+                self.paste = Some(Vec::new());
+                self.buffer.clear();
+                continue;
+            }
+
+            // A lone escape is only treated as the escape key if nothing follows it, which may
+            // be in the next chunk
+            if self.buffer == [ESC] {
+                continue;
+            }
+
+            match Event::parse_from(&self.buffer) {
+                Ok(Some(event)) => {
+                    self.events.push_back(Ok(event));
+                    self.buffer.clear();
+                }
+                Ok(None) => {
+                    // Incomplete sequence, wait for more data
+                }
+                Err(e) => {
+                    self.events.push_back(Err(e));
+                    self.buffer.clear();
+                }
+            }
+        }
+    }
+
+    /// Decodes a lone escape that's still waiting for more input as the escape key. Returns
+    /// whether there was one.
+    fn finish_escape(&mut self) -> bool {
+        if self.buffer != [ESC] {
+            return false;
+        }
+        self.buffer.clear();
+        if let Some(event) = Event::parse_from(&[ESC]).transpose() {
+            self.events.push_back(event);
         }
+        true
+    }
+
+    fn pop_event(&mut self) -> Option<io::Result<Event>> {
+        self.events.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::{FutureExt, StreamExt};
+    use terminput::{Event, KeyCode, MouseButton, MouseEvent, MouseEventKind};
+
+    use crate::{Error, InputBuffer, MemoryTerminal};
+
+    /// Ends the input and returns every event decoded from it.
+    fn events(terminal: &MemoryTerminal) -> Vec<Event> {
+        terminal.close();
+        block_on(terminal.event_stream().map(Result::unwrap).collect())
+    }
+
+    fn key_codes(events: &[Event]) -> Vec<KeyCode> {
+        events
+            .iter()
+            .map(|event| match event {
+                Event::Key(key) => key.code,
+                event => panic!("expected a key event, got {event:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_every_event_in_a_chunk() {
+        let terminal = MemoryTerminal::new(80, 24);
+        terminal.send_input("ab\x1B[A");
+        assert_eq!(
+            key_codes(&events(&terminal)),
+            [KeyCode::Char('a'), KeyCode::Char('b'), KeyCode::Up]
+        );
+    }
+
+    #[test]
+    fn keeps_sequences_split_across_chunks() {
+        let terminal = MemoryTerminal::new(80, 24);
+        terminal.send_input("\x1B[");
+        terminal.send_input("A");
+        terminal.send_input("\x1B");
+        terminal.send_input("[B");
+        assert_eq!(key_codes(&events(&terminal)), [KeyCode::Up, KeyCode::Down]);
+    }
+
+    #[test]
+    fn keeps_characters_split_across_chunks() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let bytes = "é".as_bytes();
+        terminal.send_input(&bytes[..1]);
+        terminal.send_input(&bytes[1..]);
+        assert_eq!(key_codes(&events(&terminal)), [KeyCode::Char('é')]);
+    }
+
+    #[test]
+    fn decodes_lone_escape_once_input_runs_out() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut stream = terminal.event_stream();
+        terminal.send_input("\x1B");
+        let event = stream.next().now_or_never().flatten().unwrap().unwrap();
+        assert_eq!(key_codes(&[event]), [KeyCode::Esc]);
+
+        terminal.send_input("a");
+        let event = stream.next().now_or_never().flatten().unwrap().unwrap();
+        assert_eq!(key_codes(&[event]), [KeyCode::Char('a')]);
+        assert!(stream.next().now_or_never().is_none());
+    }
+
+    #[test]
+    fn decodes_x10_mouse_bytes_above_0x7f() {
+        let terminal = MemoryTerminal::new(200, 24);
+        // Left button pressed at column 100, row 0, one byte per chunk as onBinary may send it
+        for byte in b"\x1B[M\x20\x85\x21" {
+            terminal.send_input([*byte]);
+        }
+        let events = events(&terminal);
+        assert!(
+            matches!(
+                events[..],
+                [Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Down(MouseButton::Left),
+                    column: 100,
+                    row: 0,
+                    ..
+                })]
+            ),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn decodes_paste_split_at_every_offset() {
+        let input = "\x1B[200~hi\x1B[Aé\x1B[201~";
+        for split in 0..=input.len() {
+            let terminal = MemoryTerminal::new(80, 24);
+            terminal.send_input(&input.as_bytes()[..split]);
+            terminal.send_input(&input.as_bytes()[split..]);
+            assert_eq!(
+                events(&terminal),
+                [Event::Paste("hi\x1B[Aé".to_string())],
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_keys_around_a_paste() {
+        let terminal = MemoryTerminal::new(80, 24);
+        terminal.send_input("a\x1B[200~b");
+        terminal.send_input("c\x1B[201~d");
+        let events = events(&terminal);
+        assert_eq!(key_codes(&events[..1]), [KeyCode::Char('a')]);
+        assert_eq!(events[1], Event::Paste("bc".to_string()));
+        assert_eq!(key_codes(&events[2..]), [KeyCode::Char('d')]);
+    }
+
+    #[test]
+    fn reports_dropped_input() {
+        let terminal = MemoryTerminal::new(80, 24).with_input_buffer(InputBuffer::Bounded(2));
+        for input in ["a", "b", "c", "d"] {
+            terminal.send_input(input);
+        }
+        terminal.close();
+        let results: Vec<_> = block_on(terminal.event_stream().collect());
+        assert_eq!(terminal.dropped_input_count(), 2);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[1], Ok(Event::Key(key)) if key.code == KeyCode::Char('b')));
+        let error = results[2].as_ref().unwrap_err();
+        let error = error.get_ref().and_then(|e| e.downcast_ref::<Error>());
+        assert_eq!(error, Some(&Error::InputDropped(2)));
+    }
+
+    #[test]
+    fn reports_resize_and_focus_changes() {
+        let terminal = MemoryTerminal::new(80, 24);
+        terminal.resize(100, 30);
+        terminal.set_focus(true);
+        terminal.set_focus(false);
+        assert_eq!(
+            events(&terminal),
+            [
+                Event::Resize {
+                    cols: 100,
+                    rows: 30
+                },
+                Event::FocusGained,
+                Event::FocusLost
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_input_until_closed() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut stream = terminal.event_stream();
+        assert!(stream.next().now_or_never().is_none());
+        terminal.send_input("a");
+        terminal.close();
+        assert!(stream.next().now_or_never().flatten().is_some());
+        assert!(matches!(stream.next().now_or_never(), Some(None)));
     }
 }
@@ -0,0 +1,226 @@
+#[cfg(target_arch = "wasm32")]
+use std::cell::Cell;
+#[cfg(target_arch = "wasm32")]
+use std::rc::Rc;
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use js_sys::{Function, Reflect};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::Closure;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{HtmlElement, ResizeObserver};
+#[cfg(target_arch = "wasm32")]
+use xterm_js_rs::addons::fit::FitAddon;
+
+#[cfg(target_arch = "wasm32")]
+use crate::js_terminal::read_dimensions;
+
+/// Controls how the terminal is sized relative to its parent element.
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Debug, Default)]
+pub enum FitMode {
+    /// Fit the terminal to its parent element once when it's created.
+    #[default]
+    Once,
+    /// Refit the terminal whenever its parent element changes size.
+    Auto(AutoFitOptions),
+    /// Keep the terminal at a fixed number of columns and rows.
+    Fixed { cols: u16, rows: u16 },
+}
+
+/// Options for [`FitMode::Auto`].
+#[derive(Clone, Debug)]
+pub struct AutoFitOptions {
+    debounce: Duration,
+    min_cols: u16,
+    max_cols: u16,
+    min_rows: u16,
+    max_rows: u16,
+}
+
+impl Default for AutoFitOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+            min_cols: 1,
+            max_cols: u16::MAX,
+            min_rows: 1,
+            max_rows: u16::MAX,
+        }
+    }
+}
+
+impl AutoFitOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time to wait after the last size change before refitting.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_min_cols(mut self, min_cols: u16) -> Self {
+        self.min_cols = min_cols;
+        self
+    }
+
+    pub fn with_max_cols(mut self, max_cols: u16) -> Self {
+        self.max_cols = max_cols;
+        self
+    }
+
+    pub fn with_min_rows(mut self, min_rows: u16) -> Self {
+        self.min_rows = min_rows;
+        self
+    }
+
+    pub fn with_max_rows(mut self, max_rows: u16) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Limits a proposed size to the configured bounds. The maximum wins if it's below the
+    /// minimum.
+    fn clamp(&self, cols: u16, rows: u16) -> (u16, u16) {
+        (
+            cols.max(self.min_cols).min(self.max_cols),
+            rows.max(self.min_rows).min(self.max_rows),
+        )
+    }
+
+    /// Returns the debounce in milliseconds for `setTimeout`, saturating at `i32::MAX`.
+    fn debounce_millis(&self) -> i32 {
+        i32::try_from(self.debounce.as_millis()).unwrap_or(i32::MAX)
+    }
+}
+
+/// Watches the terminal's parent element and refits the terminal when it changes size.
+///
+/// The observer is disconnected when this is dropped.
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct FitObserver {
+    observer: ResizeObserver,
+    timeout: Rc<Cell<Option<i32>>>,
+    _on_resize: Closure<dyn FnMut()>,
+    _on_timeout: Rc<Closure<dyn FnMut()>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl FitObserver {
+    pub(crate) fn observe(
+        terminal: xterm_js_rs::Terminal,
+        addon: FitAddon,
+        parent: &HtmlElement,
+        options: AutoFitOptions,
+    ) -> Result<Self, JsValue> {
+        let debounce = options.debounce_millis();
+        fit(&terminal, &addon, &options);
+
+        let timeout = Rc::new(Cell::new(None));
+        let timeout_ = timeout.clone();
+        let on_timeout = Rc::new(Closure::wrap(Box::new(move || {
+            timeout_.set(None);
+            fit(&terminal, &addon, &options);
+        }) as Box<dyn FnMut()>));
+
+        let timeout_ = timeout.clone();
+        let on_timeout_ = on_timeout.clone();
+        let on_resize = Closure::wrap(Box::new(move || {
+            let Some(window) = web_sys::window() else {
+                return;
+            };
+            if let Some(handle) = timeout_.take() {
+                window.clear_timeout_with_handle(handle);
+            }
+            let handle = window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                    (*on_timeout_).as_ref().unchecked_ref(),
+                    debounce,
+                )
+                .ok();
+            timeout_.set(handle);
+        }) as Box<dyn FnMut()>);
+
+        let observer = ResizeObserver::new(on_resize.as_ref().unchecked_ref())?;
+        observer.observe(parent);
+
+        Ok(Self {
+            observer,
+            timeout,
+            _on_resize: on_resize,
+            _on_timeout: on_timeout,
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for FitObserver {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+        if let (Some(handle), Some(window)) = (self.timeout.take(), web_sys::window()) {
+            window.clear_timeout_with_handle(handle);
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn fit(terminal: &xterm_js_rs::Terminal, addon: &FitAddon, options: &AutoFitOptions) {
+    let Some((cols, rows)) = propose_dimensions(addon) else {
+        return;
+    };
+    let (cols, rows) = options.clamp(cols, rows);
+    if cols != terminal.get_cols() as u16 || rows != terminal.get_rows() as u16 {
+        terminal.resize(cols as u32, rows as u32);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn propose_dimensions(addon: &FitAddon) -> Option<(u16, u16)> {
+    // proposeDimensions returns undefined if the terminal isn't attached to a visible element
+    let propose: Function = Reflect::get(addon, &JsValue::from_str("proposeDimensions"))
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    let dimensions = propose.call0(addon).ok()?;
+    read_dimensions(&dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::AutoFitOptions;
+
+    #[test]
+    fn clamps_to_the_limits() {
+        let options = AutoFitOptions::new()
+            .with_min_cols(20)
+            .with_max_cols(80)
+            .with_min_rows(5)
+            .with_max_rows(24);
+        assert_eq!(options.clamp(10, 2), (20, 5));
+        assert_eq!(options.clamp(50, 10), (50, 10));
+        assert_eq!(options.clamp(200, 100), (80, 24));
+        assert_eq!(AutoFitOptions::new().clamp(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn prefers_the_maximum_over_the_minimum() {
+        let options = AutoFitOptions::new().with_min_cols(100).with_max_cols(80);
+        assert_eq!(options.clamp(10, 10).0, 80);
+    }
+
+    #[test]
+    fn saturates_long_debounces() {
+        let debounce = |d| AutoFitOptions::new().with_debounce(d).debounce_millis();
+        assert_eq!(AutoFitOptions::new().debounce_millis(), 50);
+        assert_eq!(debounce(Duration::from_secs(3)), 3000);
+        assert_eq!(debounce(Duration::from_secs(u64::from(u32::MAX))), i32::MAX);
+        assert_eq!(debounce(Duration::MAX), i32::MAX);
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures::io::AsyncWrite;
+
+use crate::Host;
+
+/// Buffers output and writes it to a [`Host`] when flushed.
+///
+/// [`io::Write`] writes the buffered output immediately when flushed. The [`AsyncWrite`]
+/// implementation first waits until the host is ready for more output, so a fast render loop
+/// can't queue up more than the terminal can process.
+pub struct TerminalHandle<H> {
+    terminal: H,
+    buffer: RefCell<Vec<u8>>,
+    /// The number of bytes written to the handle so far.
+    written: usize,
+}
+
+impl<H> TerminalHandle<H> {
+    pub fn new(terminal: H) -> Self {
+        Self {
+            terminal,
+            buffer: RefCell::new(Vec::new()),
+            written: 0,
+        }
+    }
+
+    /// Returns the terminal this handle writes to.
+    pub fn terminal(&self) -> &H {
+        &self.terminal
+    }
+
+    pub(crate) fn bytes_written(&self) -> usize {
+        self.written
+    }
+}
+
+impl<H: Host> TerminalHandle<H> {
+    /// Waits until the terminal is ready to accept more output.
+    ///
+    /// Call this before drawing to keep a render loop from outrunning the terminal.
+    pub async fn writable(&self) -> io::Result<()> {
+        poll_fn(|cx| self.terminal.poll_write_ready(cx)).await
+    }
+
+    fn write_buffered(&self) -> io::Result<()> {
+        let mut buffer = self.buffer.borrow_mut();
+        // Keep a partially written character until the rest of it arrives
+        let complete_len = buffer.len() - incomplete_utf8_len(&buffer);
+        let incomplete = buffer.split_off(complete_len);
+        let complete = std::mem::replace(&mut *buffer, incomplete);
+        drop(buffer);
+
+        if complete.is_empty() {
+            return Ok(());
+        }
+        let s = match String::from_utf8(complete) {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        self.terminal.write(&s)?;
+        Ok(())
+    }
+}
+
+impl<H: Host> io::Write for TerminalHandle<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len();
+        self.buffer.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffered()
+    }
+}
+
+impl<H: Host + Unpin> AsyncWrite for TerminalHandle<H> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.terminal.poll_write_ready(cx))?;
+        let this = self.get_mut();
+        this.written += buf.len();
+        this.buffer.borrow_mut().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.terminal.poll_write_ready(cx))?;
+        Poll::Ready(self.write_buffered())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Returns the length of the incomplete UTF-8 character at the end of `bytes`, if there is one.
+fn incomplete_utf8_len(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        // Skip over continuation bytes until we find the start of the character
+        if byte & 0b1100_0000 == 0b1000_0000 {
+            continue;
+        }
+        let width = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        return if width > len { len } else { 0 };
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use futures::executor::block_on;
+
+    use super::incomplete_utf8_len;
+    use crate::MemoryTerminal;
+
+    const CHARACTERS: [&str; 3] = ["é", "€", "😀"];
+
+    #[test]
+    fn finds_incomplete_characters_cut_at_every_offset() {
+        for c in CHARACTERS {
+            for cut in 1..c.len() {
+                let data = [b"ab", &c.as_bytes()[..cut]].concat();
+                assert_eq!(incomplete_utf8_len(&data), cut, "{c} cut at {cut}");
+            }
+            assert_eq!(incomplete_utf8_len(format!("ab{c}").as_bytes()), 0);
+        }
+        assert_eq!(incomplete_utf8_len(b""), 0);
+    }
+
+    #[test]
+    fn carries_incomplete_characters_across_flushes() {
+        for c in CHARACTERS {
+            for cut in 1..c.len() {
+                let terminal = MemoryTerminal::new(80, 24);
+                let mut handle = terminal.handle();
+                handle.write_all(b"x").unwrap();
+                handle.write_all(&c.as_bytes()[..cut]).unwrap();
+                handle.flush().unwrap();
+                assert_eq!(terminal.take_output(), "x", "{c} cut at {cut}");
+
+                handle.write_all(&c.as_bytes()[cut..]).unwrap();
+                handle.flush().unwrap();
+                assert_eq!(terminal.take_output(), c, "{c} cut at {cut}");
+            }
+        }
+    }
+
+    #[test]
+    fn replaces_invalid_bytes() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut handle = terminal.handle();
+        handle.write_all(b"a\xFFb\x80c\xFF").unwrap();
+        handle.flush().unwrap();
+        assert_eq!(terminal.take_output(), "a\u{FFFD}b\u{FFFD}c\u{FFFD}");
+
+        // A character cut short by another one
+        handle.write_all(b"\xE2\x82d").unwrap();
+        handle.flush().unwrap();
+        assert_eq!(terminal.take_output(), "\u{FFFD}d");
+    }
+
+    #[test]
+    fn flushing_only_an_incomplete_character_writes_nothing() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut handle = terminal.handle();
+        handle.write_all(&"😀".as_bytes()[..2]).unwrap();
+        handle.flush().unwrap();
+        handle.flush().unwrap();
+        assert_eq!(terminal.output(), "");
+
+        handle.write_all(&"😀".as_bytes()[2..]).unwrap();
+        handle.flush().unwrap();
+        assert_eq!(terminal.output(), "😀");
+    }
+
+    #[test]
+    fn writes_on_flush() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut handle = terminal.handle();
+        handle.write_all(b"hello").unwrap();
+        assert_eq!(terminal.output(), "");
+        handle.flush().unwrap();
+        assert_eq!(terminal.take_output(), "hello");
+        assert_eq!(handle.bytes_written(), 5);
+    }
+
+    #[test]
+    fn writes_asynchronously_on_flush() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let mut handle = terminal.handle();
+        block_on(async {
+            handle.writable().await.unwrap();
+            futures::AsyncWriteExt::write_all(&mut handle, b"hello")
+                .await
+                .unwrap();
+            assert_eq!(terminal.output(), "");
+            futures::AsyncWriteExt::flush(&mut handle).await.unwrap();
+        });
+        assert_eq!(terminal.take_output(), "hello");
+    }
+}
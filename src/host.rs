@@ -0,0 +1,50 @@
+use std::io;
+use std::task::{Context, Poll};
+
+use ratatui::backend::WindowSize;
+
+use crate::TerminalInput;
+
+/// The terminal that a [`TerminalHandle`](crate::TerminalHandle),
+/// [`XtermJsBackend`](crate::XtermJsBackend) and [`EventStream`](crate::EventStream) talk to.
+///
+/// `JsTerminal` implements this for xterm.js in the browser.
+/// [`MemoryTerminal`](crate::MemoryTerminal) is an in-memory implementation that also works
+/// natively, which is useful for testing.
+pub trait Host {
+    /// Writes output to the terminal.
+    fn write(&self, data: &str) -> io::Result<()>;
+
+    /// Returns the size of the terminal in cells and pixels.
+    fn window_size(&self) -> io::Result<WindowSize>;
+
+    /// Returns the size of the terminal as `(columns, rows)`.
+    fn size(&self) -> io::Result<(u16, u16)> {
+        self.window_size()
+            .map(|s| (s.columns_rows.width, s.columns_rows.height))
+    }
+
+    /// Returns the cursor position as `(column, row)`.
+    fn cursor_position(&self) -> io::Result<(u16, u16)>;
+
+    /// Returns whether the terminal understands REP (`CSI n b`), which repeats the last
+    /// character. [`XtermJsBackend`](crate::XtermJsBackend) uses it to shorten runs of equal
+    /// cells. The default implementation returns `false`.
+    fn supports_repeat(&self) -> bool {
+        false
+    }
+
+    /// Polls whether the terminal is ready to accept more output.
+    ///
+    /// Hosts that process output asynchronously return `Pending` while too much output is still
+    /// queued. The default implementation is always ready.
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let _ = cx;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Polls for the next input from the terminal.
+    ///
+    /// Returns `None` once the terminal won't produce any more input.
+    fn poll_input(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>>;
+}
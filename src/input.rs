@@ -0,0 +1,110 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use crate::error::Error;
+use crate::query::QueryDispatcher;
+use crate::queue::Queue;
+
+/// Controls how much input is buffered before the app reads it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputBuffer {
+    /// Buffer all input until it's read. No input is ever dropped.
+    #[default]
+    Unbounded,
+    /// Buffer up to the given number of input chunks.
+    ///
+    /// Input that arrives while the buffer is full is dropped. Dropped input is counted and
+    /// reported through the event stream as an [`Error::InputDropped`](crate::Error::InputDropped)
+    /// error.
+    Bounded(usize),
+}
+
+/// Input received from a [`Host`](crate::Host).
+#[derive(Debug)]
+pub enum TerminalInput {
+    /// Raw input bytes, such as xterm's `onData` and `onBinary` payloads.
+    Data(Vec<u8>),
+    /// New dimensions in columns and rows.
+    Resize(u16, u16),
+    /// The terminal gained or lost focus.
+    Focus(bool),
+    /// Number of inputs dropped at this point because the buffer was full.
+    Dropped(usize),
+    /// An error that occurred while receiving input.
+    Error(Error),
+}
+
+/// Single-threaded queue that carries input from the host's callbacks to the event stream.
+#[derive(Clone)]
+pub(crate) struct InputQueue {
+    inputs: Queue<TerminalInput>,
+    buffer: InputBuffer,
+    dropped: Rc<Cell<usize>>,
+    queries: QueryDispatcher,
+}
+
+impl InputQueue {
+    pub(crate) fn new(buffer: InputBuffer) -> Self {
+        Self {
+            inputs: Queue::new(),
+            buffer,
+            dropped: Rc::default(),
+            queries: QueryDispatcher::default(),
+        }
+    }
+
+    /// Returns the dispatcher that takes query replies out of this queue's input.
+    pub(crate) fn queries(&self) -> QueryDispatcher {
+        self.queries.clone()
+    }
+
+    pub(crate) fn push(&self, input: TerminalInput) {
+        if self.inputs.is_closed() {
+            return;
+        }
+        let input = match input {
+            TerminalInput::Data(data) => {
+                let data = self.queries.filter(data);
+                if data.is_empty() {
+                    return;
+                }
+                TerminalInput::Data(data)
+            }
+            input => input,
+        };
+        self.inputs.update(|inputs| {
+            let full = match self.buffer {
+                InputBuffer::Unbounded => false,
+                InputBuffer::Bounded(capacity) => inputs.len() >= capacity,
+            };
+            if !full {
+                inputs.push_back(input);
+                return;
+            }
+            self.dropped.set(self.dropped.get() + 1);
+            // The marker may exceed the capacity so the stream still sees where input was lost
+            if let Some(TerminalInput::Dropped(count)) = inputs.back_mut() {
+                *count += 1;
+            } else {
+                inputs.push_back(TerminalInput::Dropped(1));
+            }
+        });
+    }
+
+    /// Closes the queue. Remaining input can still be read, after which the queue ends.
+    pub(crate) fn close(&self) {
+        self.inputs.close();
+        // Nothing can answer waiting queries anymore
+        self.queries.cancel();
+    }
+
+    pub(crate) fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>> {
+        self.inputs.poll_next(cx)
+    }
+
+    /// Total number of inputs dropped because the buffer was full.
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped.get()
+    }
+}
@@ -1,104 +1,743 @@
-use std::cell::{OnceCell, RefCell};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::poll_fn;
 use std::io;
-use std::sync::{Mutex, OnceLock};
-use std::task::{Context, Poll};
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, ready};
 
-use crossterm::terminal::WindowSize;
-use futures::StreamExt;
-use futures::channel::mpsc;
-use wasm_bindgen::JsCast;
+use js_sys::{Array, Function, Object, Reflect};
+use ratatui::backend::WindowSize;
+use ratatui::layout::Size;
 use wasm_bindgen::prelude::Closure;
-use web_sys::HtmlElement;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{EventTarget, HtmlElement};
 use xterm_js_rs::addons::fit::FitAddon;
 
-thread_local! {
-    static TERMINAL: OnceCell<xterm_js_rs::Terminal> = const { OnceCell::new() };
+use crate::backend::{BEGIN_SYNCHRONIZED_UPDATE, END_SYNCHRONIZED_UPDATE};
+use crate::chunk::split_chunks;
+use crate::error::Error;
+use crate::fit::{FitMode, FitObserver};
+use crate::input::InputQueue;
+use crate::link_click::{LinkClick, LinkClicks, LinkQueue};
+use crate::writes::{FlowControl, PendingWrites};
+use crate::{EventStream, Host, InputBuffer, TerminalHandle, TerminalInput, TerminalQueries};
+
+/// Creates a new xterm.js terminal inside `parent`.
+///
+/// Each call creates an independent terminal, so several terminals can live on the same page.
+pub fn init_terminal(
+    options: &xterm_js_rs::TerminalOptions,
+    parent: HtmlElement,
+) -> Result<JsTerminal, Error> {
+    init_terminal_with_config(options, parent, TerminalConfig::default())
 }
 
-static DATA_CHANNEL: OnceLock<Mutex<mpsc::Receiver<String>>> = OnceLock::new();
+/// Creates a new xterm.js terminal inside `parent` using the given [`TerminalConfig`].
+pub fn init_terminal_with_config(
+    options: &xterm_js_rs::TerminalOptions,
+    parent: HtmlElement,
+    config: TerminalConfig,
+) -> Result<JsTerminal, Error> {
+    let input = InputQueue::new(config.input_buffer);
+    let terminal = xterm_js_rs::Terminal::new(options);
+    let mut listeners = Listeners::default();
+
+    let input_ = input.clone();
+    listeners.subscribe(&terminal, "onData", move |e| {
+        input_.push(match e.as_string() {
+            Some(data) => TerminalInput::Data(data.into_bytes()),
+            None => TerminalInput::Error(Error::NonStringPayload),
+        });
+    })?;
+
+    // onBinary data is a string where each character represents a single byte
+    let input_ = input.clone();
+    listeners.subscribe(&terminal, "onBinary", move |e| {
+        input_.push(match e.as_string() {
+            Some(data) => TerminalInput::Data(data.chars().map(|c| c as u8).collect()),
+            None => TerminalInput::Error(Error::NonStringPayload),
+        });
+    })?;
+
+    // onResize fires with an object of the form { cols, rows }
+    let input_ = input.clone();
+    listeners.subscribe(&terminal, "onResize", move |e| {
+        if let Some((cols, rows)) = read_dimensions(&e) {
+            input_.push(TerminalInput::Resize(cols, rows));
+        }
+    })?;
+
+    let link_clicks = LinkQueue::default();
+    if config.link_handler != LinkHandler::Default {
+        listeners.set_link_handler(&terminal, config.link_handler, link_clicks.clone())?;
+    }
+
+    let addon = FitAddon::new();
+    terminal.load_addon(addon.clone().dyn_into::<FitAddon>().unwrap().into());
+    if !matches!(config.fit_mode, FitMode::Fixed { .. }) {
+        addon.fit();
+    }
+
+    terminal.open(parent.clone());
+    listeners.fit_observer = match config.fit_mode {
+        FitMode::Once => None,
+        FitMode::Fixed { cols, rows } => {
+            terminal.resize(cols as u32, rows as u32);
+            None
+        }
+        FitMode::Auto(fit_options) => Some(FitObserver::observe(
+            terminal.clone(),
+            addon,
+            &parent,
+            fit_options,
+        )?),
+    };
+    terminal.focus();
 
-pub(crate) fn with_terminal<F, T>(f: F) -> T
-where
-    F: FnOnce(&xterm_js_rs::Terminal) -> T,
-{
-    TERMINAL.with(|t| f(t.get().unwrap()))
+    // The textarea only exists once the terminal is opened
+    if let Some(textarea) = Reflect::get(&terminal, &JsValue::from_str("textarea"))
+        .ok()
+        .and_then(|t| t.dyn_into::<EventTarget>().ok())
+    {
+        for (event, focused) in [("focus", true), ("blur", false)] {
+            let input = input.clone();
+            let terminal_ = terminal.clone();
+            listeners.add_event_listener(&textarea, event, move || {
+                // xterm sends its own focus reports when the app has enabled them
+                if !sends_focus_reports(&terminal_) {
+                    input.push(TerminalInput::Focus(focused));
+                }
+            })?;
+        }
+    }
+
+    Ok(JsTerminal {
+        inner: Rc::new(Inner {
+            terminal,
+            input,
+            listeners: RefCell::new(Some(listeners)),
+            writes: Rc::new(PendingWrites::new(config.flow_control)),
+            presentation: config.presentation,
+            frame: RefCell::default(),
+            max_chunk_size: config.max_chunk_size,
+            chunks: RefCell::default(),
+            link_clicks,
+        }),
+    })
 }
 
-pub fn init_terminal(options: &xterm_js_rs::TerminalOptions, parent: HtmlElement) {
-    TERMINAL.with(|t| {
-        let (mut tx, rx) = mpsc::channel(32);
-        let mut tx_ = tx.clone();
-        let terminal = xterm_js_rs::Terminal::new(options);
+/// An xterm.js event subscription's disposable and its callback.
+type Subscription = (JsValue, Closure<dyn FnMut(JsValue)>);
+
+/// A DOM event listener's target, event name and callback.
+type EventListener = (EventTarget, &'static str, Closure<dyn FnMut()>);
 
-        let callback = Closure::wrap(Box::new(move |e: xterm_js_rs::Event| {
-            tx_.try_send(e.as_string().unwrap()).ok();
-        }) as Box<dyn FnMut(_)>);
-        terminal.on_data(callback.as_ref().unchecked_ref());
-        callback.forget();
+/// Callbacks registered with xterm.js and the DOM.
+///
+/// The closures are kept alive here until the listeners are removed.
+#[derive(Default)]
+struct Listeners {
+    subscriptions: Vec<Subscription>,
+    event_listeners: Vec<EventListener>,
+    fit_observer: Option<FitObserver>,
+    /// The terminal's options object, if a link handler was set on it.
+    link_options: Option<JsValue>,
+    link_activate: Option<Closure<dyn FnMut(JsValue, JsValue)>>,
+}
+
+impl Listeners {
+    /// Subscribes to an xterm.js event such as `onData`.
+    fn subscribe<F>(
+        &mut self,
+        terminal: &xterm_js_rs::Terminal,
+        event: &str,
+        f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(JsValue) + 'static,
+    {
+        let callback = Closure::wrap(Box::new(f) as Box<dyn FnMut(JsValue)>);
+        // Subscribing returns an IDisposable that removes the listener
+        let disposable = call_method(terminal, event, &[callback.as_ref()])?;
+        self.subscriptions.push((disposable, callback));
+        Ok(())
+    }
 
-        let callback = Closure::wrap(Box::new(move |e: xterm_js_rs::Event| {
-            tx.try_send(e.as_string().unwrap()).ok();
-        }) as Box<dyn FnMut(_)>);
-        terminal.on_binary(callback.as_ref().unchecked_ref());
-        callback.forget();
+    fn add_event_listener<F>(
+        &mut self,
+        target: &EventTarget,
+        event: &'static str,
+        f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut() + 'static,
+    {
+        let callback = Closure::wrap(Box::new(f) as Box<dyn FnMut()>);
+        target.add_event_listener_with_callback(event, callback.as_ref().unchecked_ref())?;
+        self.event_listeners.push((target.clone(), event, callback));
+        Ok(())
+    }
 
-        DATA_CHANNEL.set(Mutex::new(rx)).unwrap();
+    /// Sets xterm's `linkHandler` option, which is called when an OSC 8 hyperlink is clicked.
+    fn set_link_handler(
+        &mut self,
+        terminal: &xterm_js_rs::Terminal,
+        handler: LinkHandler,
+        clicks: LinkQueue,
+    ) -> Result<(), Error> {
+        // activate(event, text, range) receives the link's URL as the text
+        let activate = Closure::wrap(Box::new(move |_event: JsValue, text: JsValue| {
+            let Some(url) = text.as_string() else {
+                return;
+            };
+            match handler {
+                LinkHandler::Default => {}
+                LinkHandler::Open => {
+                    if let Some(window) = web_sys::window() {
+                        window
+                            .open_with_url_and_target_and_features(
+                                &url,
+                                "_blank",
+                                "noopener,noreferrer",
+                            )
+                            .ok();
+                    }
+                }
+                LinkHandler::Event => clicks.push(LinkClick { url }),
+            }
+        }) as Box<dyn FnMut(JsValue, JsValue)>);
 
-        let addon = FitAddon::new();
-        terminal.load_addon(addon.clone().dyn_into::<FitAddon>().unwrap().into());
-        addon.fit();
+        let link_handler = Object::new();
+        Reflect::set(
+            &link_handler,
+            &JsValue::from_str("activate"),
+            activate.as_ref(),
+        )?;
+        // Only the app knows what to do with other schemes, so only let them through to it
+        Reflect::set(
+            &link_handler,
+            &JsValue::from_str("allowNonHttpProtocols"),
+            &JsValue::from_bool(handler == LinkHandler::Event),
+        )?;
+        let options = Reflect::get(terminal, &JsValue::from_str("options"))?;
+        Reflect::set(&options, &JsValue::from_str("linkHandler"), &link_handler)?;
+        self.link_options = Some(options);
+        self.link_activate = Some(activate);
+        Ok(())
+    }
 
-        terminal.open(parent);
-        terminal.focus();
-        if t.set(terminal).is_err() {
-            panic!();
+    fn remove(self) {
+        for (disposable, _callback) in &self.subscriptions {
+            call_method(disposable, "dispose", &[]).ok();
+        }
+        for (target, event, callback) in &self.event_listeners {
+            target
+                .remove_event_listener_with_callback(event, callback.as_ref().unchecked_ref())
+                .ok();
+        }
+        if let Some(options) = &self.link_options {
+            // Fall back to xterm's default handling
+            Reflect::set(options, &JsValue::from_str("linkHandler"), &JsValue::NULL).ok();
         }
-    });
+    }
+}
+
+/// Calls `target[name](...args)`, catching any exception thrown by the method.
+fn call_method(target: &JsValue, name: &str, args: &[&JsValue]) -> Result<JsValue, Error> {
+    let method: Function = Reflect::get(target, &JsValue::from_str(name))?.dyn_into()?;
+    let args: Array = args.iter().collect();
+    Ok(method.apply(target, &args)?)
+}
+
+/// Controls when flushed output is handed to xterm.js.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Presentation {
+    /// Write output to xterm.js as soon as it's flushed.
+    #[default]
+    Immediate,
+    /// Collect flushed output and write it to xterm.js once per animation frame.
+    ///
+    /// Frames drawn between two animation frames are written together, so the browser only
+    /// renders the latest state. This suits apps that redraw on every event or tick.
+    ///
+    /// Browsers don't run animation frames while the page is hidden. Once more output than the
+    /// [`FlowControl`] high watermark is collected, it's written right away, so flow control
+    /// still slows down an app that keeps drawing.
+    AnimationFrame,
+}
+
+/// What happens when an OSC 8 hyperlink in the terminal is clicked.
+///
+/// See [`Hyperlinks`](crate::Hyperlinks) for drawing links.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LinkHandler {
+    /// Leave it to xterm.js, which asks for confirmation before opening the link in a new tab.
+    #[default]
+    Default,
+    /// Open the link in a new tab right away.
+    Open,
+    /// Don't open the link, but deliver the click to the app through
+    /// [`JsTerminal::link_clicks`]. Links with any scheme are delivered, not just HTTP(S).
+    Event,
+}
+
+/// Additional configuration for [`init_terminal_with_config`].
+#[derive(Clone, Debug, Default)]
+pub struct TerminalConfig {
+    fit_mode: FitMode,
+    input_buffer: InputBuffer,
+    flow_control: FlowControl,
+    presentation: Presentation,
+    max_chunk_size: Option<usize>,
+    link_handler: LinkHandler,
+}
+
+impl TerminalConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the terminal is sized relative to its parent element.
+    pub fn with_fit_mode(mut self, fit_mode: FitMode) -> Self {
+        self.fit_mode = fit_mode;
+        self
+    }
+
+    /// Sets how much input is buffered before the app reads it.
+    pub fn with_input_buffer(mut self, input_buffer: InputBuffer) -> Self {
+        self.input_buffer = input_buffer;
+        self
+    }
+
+    /// Sets the watermarks used to hold back output while xterm.js is busy. Defaults to
+    /// 128 KiB and 16 KiB.
+    pub fn with_flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// Sets when flushed output is written to xterm.js. Defaults to
+    /// [`Presentation::Immediate`].
+    pub fn with_presentation(mut self, presentation: Presentation) -> Self {
+        self.presentation = presentation;
+        self
+    }
+
+    /// Splits writes larger than `max_chunk_size` bytes into smaller chunks that are handed to
+    /// xterm.js one at a time, so a huge frame doesn't block the page while it's parsed.
+    ///
+    /// Chunks are split between escape sequences and wrapped in a synchronized update, so
+    /// terminals that support it still show the frame all at once. Output that already begins or
+    /// ends a synchronized update itself, such as the backend's frames with synchronized output
+    /// enabled, isn't wrapped again. By default, writes aren't split.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = Some(max_chunk_size);
+        self
+    }
+
+    /// Sets what happens when a hyperlink is clicked. Defaults to [`LinkHandler::Default`].
+    pub fn with_link_handler(mut self, link_handler: LinkHandler) -> Self {
+        self.link_handler = link_handler;
+        self
+    }
 }
 
-pub(crate) fn poll_next_event(cx: &mut Context<'_>) -> Poll<Option<String>> {
-    DATA_CHANNEL
-        .get()
-        .unwrap()
-        .lock()
-        .unwrap()
-        .poll_next_unpin(cx)
+/// Reads an object of the form `{ cols, rows }`.
+pub(crate) fn read_dimensions(value: &JsValue) -> Option<(u16, u16)> {
+    let dimension = |key: &str| {
+        Reflect::get(value, &JsValue::from_str(key))
+            .ok()
+            .and_then(|v| v.as_f64())
+            .filter(|v| v.is_finite() && *v >= 1.0)
+            .map(|v| v as u16)
+    };
+    Some((dimension("cols")?, dimension("rows")?))
 }
 
-pub fn window_size() -> io::Result<WindowSize> {
-    Ok(with_terminal(|t| WindowSize {
-        rows: t.get_rows() as u16,
-        columns: t.get_cols() as u16,
-        width: t.get_element().client_width() as u16,
-        height: t.get_element().client_height() as u16,
-    }))
+/// Returns whether focus reporting (DECSET 1004) is enabled.
+fn sends_focus_reports(terminal: &xterm_js_rs::Terminal) -> bool {
+    Reflect::get(terminal, &JsValue::from_str("modes"))
+        .and_then(|modes| Reflect::get(&modes, &JsValue::from_str("sendFocusMode")))
+        .map(|mode| mode.is_truthy())
+        .unwrap_or(false)
 }
 
-pub(crate) fn size() -> io::Result<(u16, u16)> {
-    window_size().map(|s| (s.columns, s.rows))
+struct Inner {
+    terminal: xterm_js_rs::Terminal,
+    input: InputQueue,
+    /// `None` once the terminal is disposed.
+    listeners: RefCell<Option<Listeners>>,
+    writes: Rc<PendingWrites>,
+    presentation: Presentation,
+    frame: RefCell<PendingFrame>,
+    max_chunk_size: Option<usize>,
+    chunks: RefCell<PendingChunks>,
+    link_clicks: LinkQueue,
 }
 
-pub fn cursor_position() -> io::Result<(u16, u16)> {
-    Ok(with_terminal(|t| {
-        let active = t.get_buffer().get_active();
-        (active.get_cursor_x() as u16, active.get_cursor_y() as u16)
-    }))
+/// Chunks of a large write that haven't been handed to xterm.js yet.
+#[derive(Default)]
+struct PendingChunks {
+    queue: VecDeque<String>,
+    /// Whether a chunk is being parsed. Later writes are queued behind it to keep their order.
+    writing: bool,
 }
 
+/// Output collected for the next animation frame.
 #[derive(Default)]
-pub struct TerminalHandle {
-    buffer: RefCell<Vec<u8>>,
+struct PendingFrame {
+    output: String,
+    scheduled: bool,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // The closures are about to be dropped, so xterm must not call them anymore. The terminal
+        // itself stays on the page unless it was disposed.
+        if let Some(listeners) = self.listeners.get_mut().take() {
+            listeners.remove();
+        }
+    }
+}
+
+/// An xterm.js terminal created by [`init_terminal`].
+///
+/// This is a cheaply cloneable reference to the terminal. All clones refer to the same
+/// terminal instance. When the last clone is dropped, the terminal stops delivering input but
+/// stays on the page. Use [`JsTerminal::dispose`] to remove it.
+#[derive(Clone)]
+pub struct JsTerminal {
+    inner: Rc<Inner>,
 }
 
-impl io::Write for TerminalHandle {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.buffer.borrow_mut().write(buf)
+impl JsTerminal {
+    /// Returns the underlying xterm.js terminal.
+    pub fn xterm(&self) -> &xterm_js_rs::Terminal {
+        &self.inner.terminal
+    }
+
+    /// Creates a new output handle that writes to this terminal.
+    pub fn handle(&self) -> TerminalHandle<Self> {
+        TerminalHandle::new(self.clone())
+    }
+
+    /// Creates a handle for sending queries such as the cursor position or device attributes to
+    /// this terminal.
+    ///
+    /// xterm.js answers through its input. The replies are picked out before they reach any
+    /// [`EventStream`], so they never appear as key presses.
+    pub fn queries(&self) -> TerminalQueries<Self> {
+        TerminalQueries::new(self.clone(), self.inner.input.queries())
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        let s = String::from_utf8(self.buffer.replace(Vec::new()))
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        with_terminal(|t| t.write(&s));
+    /// Creates a stream of input events from this terminal.
+    ///
+    /// Only one stream can read from the terminal at a time. See [`EventStream`].
+    pub fn event_stream(&self) -> EventStream<Self> {
+        EventStream::new(self.clone())
+    }
+
+    /// Creates a stream of clicks on hyperlinks in this terminal.
+    ///
+    /// Clicks are only delivered if the terminal was created with [`LinkHandler::Event`]. Like
+    /// [`event_stream`](Self::event_stream), only one stream can read the clicks at a time.
+    pub fn link_clicks(&self) -> LinkClicks {
+        LinkClicks::new(self.inner.link_clicks.clone())
+    }
+
+    /// Disposes the terminal and removes it from the page.
+    ///
+    /// This removes all listeners registered by this crate and ends any [`EventStream`] reading
+    /// from the terminal. Disposing an already disposed terminal does nothing.
+    pub fn dispose(&self) -> Result<(), Error> {
+        let Some(listeners) = self.inner.listeners.borrow_mut().take() else {
+            return Ok(());
+        };
+        listeners.remove();
+        self.inner.input.close();
+        self.inner.link_clicks.close();
+        // Write callbacks aren't called after this, so nothing would wake anyone waiting on them
+        self.inner.writes.reset();
+        self.inner.frame.borrow_mut().output.clear();
+        self.inner.chunks.borrow_mut().queue.clear();
+        // Disposing the terminal also disposes any loaded addons
+        call_method(&self.inner.terminal, "dispose", &[])?;
+        Ok(())
+    }
+
+    /// Returns whether [`dispose`](Self::dispose) has been called.
+    pub fn is_disposed(&self) -> bool {
+        self.inner.listeners.borrow().is_none()
+    }
+
+    fn ensure_active(&self) -> Result<(), Error> {
+        if self.is_disposed() {
+            return Err(Error::Disposed);
+        }
+        Ok(())
+    }
+
+    /// Waits until xterm.js has finished parsing everything written to it so far.
+    ///
+    /// xterm.js parses output asynchronously, so queries such as the cursor position only
+    /// reflect previous writes once this completes.
+    pub async fn wait_for_writes(&self) {
+        self.present_frame().ok();
+        poll_fn(|cx| self.inner.writes.poll_done(cx)).await;
+    }
+
+    /// Returns the cursor position once xterm.js has finished parsing all output written so far.
+    pub async fn synced_cursor_position(&self) -> io::Result<(u16, u16)> {
+        self.wait_for_writes().await;
+        self.cursor_position()
+    }
+
+    /// Collects `data` for the next animation frame, requesting one if needed.
+    fn queue_frame(&self, data: &str) -> Result<(), Error> {
+        let mut frame = self.inner.frame.borrow_mut();
+        frame.output.push_str(data);
+        if frame.scheduled {
+            return Ok(());
+        }
+        // A weak reference lets the terminal be dropped before the frame runs
+        let inner = Rc::downgrade(&self.inner);
+        let callback = Closure::once_into_js(move || {
+            if let Some(inner) = Weak::upgrade(&inner) {
+                let terminal = JsTerminal { inner };
+                terminal.inner.frame.borrow_mut().scheduled = false;
+                terminal.present_frame().ok();
+            }
+        });
+        let window = web_sys::window().ok_or_else(|| Error::Js("no window".to_string()))?;
+        window.request_animation_frame(callback.unchecked_ref())?;
+        frame.scheduled = true;
+        Ok(())
+    }
+
+    /// Writes the output collected for the next animation frame immediately.
+    fn present_frame(&self) -> io::Result<()> {
+        let output = std::mem::take(&mut self.inner.frame.borrow_mut().output);
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.write_now(&output)
+    }
+
+    /// Writes `data` to xterm.js, splitting it into chunks if it's too large.
+    fn write_now(&self, data: &str) -> io::Result<()> {
+        self.ensure_active()?;
+        let mut chunks = self.inner.chunks.borrow_mut();
+        let max_chunk_size = match self.inner.max_chunk_size {
+            Some(max) if chunks.writing || data.len() > max => max,
+            _ => {
+                drop(chunks);
+                self.inner.writes.start(data.len());
+                return self.write_js(data, |_| {});
+            }
+        };
+
+        // Queued chunks count as pending so flow control and wait_for_writes include them
+        let mut queue_chunk = |chunk: String| {
+            self.inner.writes.start(chunk.len());
+            chunks.queue.push_back(chunk);
+        };
+        if data.len() > max_chunk_size {
+            // Synchronized updates don't nest, so output that already manages its own (like the
+            // backend's frames) is left alone, as an inner end would cut the outer update short
+            let synchronized =
+                data.contains(BEGIN_SYNCHRONIZED_UPDATE) || data.contains(END_SYNCHRONIZED_UPDATE);
+            let data = if synchronized {
+                Cow::Borrowed(data)
+            } else {
+                Cow::Owned(format!(
+                    "{BEGIN_SYNCHRONIZED_UPDATE}{data}{END_SYNCHRONIZED_UPDATE}"
+                ))
+            };
+            for chunk in split_chunks(&data, max_chunk_size) {
+                queue_chunk(chunk.to_string());
+            }
+        } else {
+            queue_chunk(data.to_string());
+        }
+        if chunks.writing {
+            return Ok(());
+        }
+        chunks.writing = true;
+        drop(chunks);
+        self.write_next_chunk()
+    }
+
+    /// Hands the next queued chunk to xterm.js. The following chunk is written once xterm.js
+    /// has parsed this one, which happens on a later turn of the event loop.
+    fn write_next_chunk(&self) -> io::Result<()> {
+        let mut chunks = self.inner.chunks.borrow_mut();
+        let Some(chunk) = chunks.queue.pop_front() else {
+            chunks.writing = false;
+            return Ok(());
+        };
+        drop(chunks);
+        self.write_js(&chunk, |inner| {
+            JsTerminal { inner }.write_next_chunk().ok();
+        })
+        .inspect_err(|_| {
+            // Nothing would continue with the remaining chunks
+            let mut chunks = self.inner.chunks.borrow_mut();
+            for chunk in chunks.queue.drain(..) {
+                self.inner.writes.finish(chunk.len());
+            }
+            chunks.writing = false;
+        })
+    }
+
+    /// Hands any queued chunks to xterm.js immediately.
+    fn write_queued_chunks(&self) -> io::Result<()> {
+        let queue = std::mem::take(&mut self.inner.chunks.borrow_mut().queue);
+        for chunk in queue {
+            self.write_js(&chunk, |_| {})?;
+        }
         Ok(())
     }
+
+    /// Writes `data` to xterm.js, marking it as finished once xterm.js has parsed it. The write
+    /// must already be counted as pending.
+    ///
+    /// `on_parsed` is called after that, if the terminal is still alive.
+    fn write_js(&self, data: &str, on_parsed: impl FnOnce(Rc<Inner>) + 'static) -> io::Result<()> {
+        let writes = self.inner.writes.clone();
+        let len = data.len();
+        // Called by xterm once it has parsed the data
+        let callback = Closure::once_into_js({
+            let writes = writes.clone();
+            let inner = Rc::downgrade(&self.inner);
+            move || {
+                writes.finish(len);
+                if let Some(inner) = Weak::upgrade(&inner) {
+                    on_parsed(inner);
+                }
+            }
+        });
+        if let Err(e) = call_method(
+            &self.inner.terminal,
+            "write",
+            &[&JsValue::from_str(data), &callback],
+        ) {
+            writes.finish(len);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Makes xterm.js parse any pending writes immediately.
+    ///
+    /// xterm.js doesn't expose this publicly, so this relies on its internal write buffer,
+    /// `_core._writeBuffer.writeSync`. That exists in xterm.js 4.x and 5.x and was checked
+    /// against 5.5.0, the version the examples use. If it isn't available, pending writes are left
+    /// for xterm.js to parse later.
+    fn parse_pending_writes(&self) {
+        if self.inner.writes.is_empty() {
+            return;
+        }
+        let write_buffer = Reflect::get(&self.inner.terminal, &JsValue::from_str("_core"))
+            .and_then(|core| Reflect::get(&core, &JsValue::from_str("_writeBuffer")));
+        if let Ok(write_buffer) = write_buffer {
+            // writeSync processes all queued chunks before the given data
+            call_method(&write_buffer, "writeSync", &[&JsValue::from_str("")]).ok();
+        }
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakJsTerminal {
+        WeakJsTerminal(Rc::downgrade(&self.inner))
+    }
+
+    /// Returns the total number of inputs dropped because the input buffer was full.
+    ///
+    /// This is always zero when using [`InputBuffer::Unbounded`].
+    pub fn dropped_input_count(&self) -> usize {
+        self.inner.input.dropped()
+    }
+}
+
+/// A reference to a [`JsTerminal`] that doesn't keep it alive.
+#[derive(Clone)]
+pub(crate) struct WeakJsTerminal(Weak<Inner>);
+
+impl WeakJsTerminal {
+    /// Writes `data` straight to xterm.js, skipping presentation, chunking and flow control.
+    ///
+    /// This doesn't wait for any of the terminal's state to be released, so it can be called
+    /// while that state is borrowed, such as from a panic hook.
+    pub(crate) fn write_now(&self, data: &str) -> Result<(), Error> {
+        let inner = self.0.upgrade().ok_or(Error::Disposed)?;
+        if matches!(inner.listeners.try_borrow().as_deref(), Ok(None)) {
+            return Err(Error::Disposed);
+        }
+        call_method(&inner.terminal, "write", &[&JsValue::from_str(data)])?;
+        Ok(())
+    }
+}
+
+impl Host for JsTerminal {
+    /// Writes `data` to the terminal, catching any exception thrown by xterm.js.
+    ///
+    /// With [`Presentation::AnimationFrame`], the data is written with the next animation frame.
+    fn write(&self, data: &str) -> io::Result<()> {
+        self.ensure_active()?;
+        match self.inner.presentation {
+            Presentation::Immediate => self.write_now(data),
+            Presentation::AnimationFrame => {
+                self.queue_frame(data)?;
+                // Animation frames don't run while the page is hidden, so write large output now
+                // instead of letting it pile up. It then counts as pending, which holds back
+                // `writable`.
+                let high = self.inner.writes.flow_control().high;
+                if self.inner.frame.borrow().output.len() > high {
+                    self.present_frame()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn window_size(&self) -> io::Result<WindowSize> {
+        self.ensure_active()?;
+        let t = &self.inner.terminal;
+        Ok(WindowSize {
+            columns_rows: Size::new(t.get_cols() as u16, t.get_rows() as u16),
+            pixels: Size::new(
+                t.get_element().client_width() as u16,
+                t.get_element().client_height() as u16,
+            ),
+        })
+    }
+
+    /// Returns the cursor position, parsing any pending writes first so the position is current.
+    ///
+    /// Parsing them relies on xterm.js internals. If those are missing in the xterm.js version
+    /// in use, the position may not include the latest writes yet. Use
+    /// [`synced_cursor_position`](JsTerminal::synced_cursor_position) to wait for them instead.
+    fn cursor_position(&self) -> io::Result<(u16, u16)> {
+        self.ensure_active()?;
+        self.present_frame()?;
+        self.write_queued_chunks()?;
+        self.parse_pending_writes();
+        let active = self.inner.terminal.get_buffer().get_active();
+        Ok((active.get_cursor_x() as u16, active.get_cursor_y() as u16))
+    }
+
+    fn supports_repeat(&self) -> bool {
+        true
+    }
+
+    /// Waits while xterm.js has more output queued than the high watermark allows.
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.ensure_active()?;
+        ready!(self.inner.writes.poll_ready(cx));
+        // The terminal may have been disposed while waiting
+        Poll::Ready(self.ensure_active().map_err(Into::into))
+    }
+
+    fn poll_input(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>> {
+        self.inner.input.poll_next(cx)
+    }
 }
@@ -1,15 +1,63 @@
-#[cfg(target_arch = "wasm32")]
 pub use backend::XtermJsBackend;
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "emulator")]
+pub use emulator::EmulatedTerminal;
+pub use error::Error;
 pub use event::EventStream;
 #[cfg(target_arch = "wasm32")]
+pub use fit::{AutoFitOptions, FitMode};
+pub use handle::TerminalHandle;
+pub use host::Host;
+pub use input::{InputBuffer, TerminalInput};
+#[cfg(target_arch = "wasm32")]
 pub use js_terminal::*;
+pub use link::Hyperlinks;
+#[cfg(target_arch = "wasm32")]
+pub use link_click::{LinkClick, LinkClicks};
+pub use memory::MemoryTerminal;
+pub use mode::TerminalMode;
+#[cfg(target_arch = "wasm32")]
+pub use panic_hook::install_panic_hook;
+pub use query::{ModeStatus, TerminalQueries};
+pub use terminput;
+#[cfg(feature = "emulator")]
+pub use testing::{
+    CellMismatch, CellState, ScreenHarness, ScreenMismatch, SnapshotFormat, UPDATE_SNAPSHOTS_VAR,
+};
+#[cfg(feature = "emulator")]
+pub use vt100;
+#[cfg(target_arch = "wasm32")]
+pub use writes::FlowControl;
 #[cfg(target_arch = "wasm32")]
 pub use xterm_js_rs as xterm;
 
-#[cfg(target_arch = "wasm32")]
+mod ansi;
 mod backend;
-#[cfg(target_arch = "wasm32")]
+#[cfg(any(target_arch = "wasm32", test))]
+mod chunk;
+#[cfg(feature = "emulator")]
+mod emulator;
+mod encoder;
+mod error;
 mod event;
+#[cfg(any(target_arch = "wasm32", test))]
+mod fit;
+mod handle;
+mod host;
+mod input;
 #[cfg(target_arch = "wasm32")]
 mod js_terminal;
+mod link;
+#[cfg(target_arch = "wasm32")]
+mod link_click;
+mod memory;
+mod mode;
+#[cfg(target_arch = "wasm32")]
+mod panic_hook;
+mod query;
+mod queue;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "emulator")]
+mod testing;
+#[cfg(any(target_arch = "wasm32", test))]
+mod writes;
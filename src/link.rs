@@ -0,0 +1,101 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ratatui::layout::Rect;
+
+/// Hyperlinks for the next frame drawn by an [`XtermJsBackend`](crate::XtermJsBackend).
+///
+/// Get one from [`XtermJsBackend::hyperlinks`](crate::XtermJsBackend::hyperlinks) and add the
+/// areas of the widgets that should link somewhere while rendering. The links are written as
+/// OSC 8 hyperlinks, which xterm.js underlines on hover and opens on click. See
+/// `TerminalConfig::with_link_handler` for handling the clicks in the app instead.
+///
+/// Links only last for one frame, so add them every time the widgets they belong to are
+/// rendered. Where areas overlap, the link added last wins.
+///
+/// This is a cheaply cloneable reference. All clones refer to the same links.
+///
+/// # Example
+///
+/// ```rust
+/// use ratatui::Terminal;
+/// use ratatui::layout::Rect;
+/// use ratatui::widgets::Paragraph;
+/// use ratatui_xterm_js::{MemoryTerminal, XtermJsBackend};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let terminal = MemoryTerminal::new(80, 24);
+/// let backend = XtermJsBackend::new(terminal.handle());
+/// let links = backend.hyperlinks();
+/// let mut terminal = Terminal::new(backend)?;
+/// terminal.draw(|frame| {
+///     let area = Rect::new(0, 0, 4, 1);
+///     frame.render_widget(Paragraph::new("docs"), area);
+///     links.add(area, "https://docs.rs/ratatui");
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Hyperlinks {
+    links: Rc<RefCell<Vec<Link>>>,
+}
+
+impl Hyperlinks {
+    /// Links the cells in `area` to `url` in the next frame.
+    pub fn add(&self, area: Rect, url: impl Into<String>) {
+        let url = url.into();
+        // Control characters would end the escape sequence early
+        let url = if url.contains(char::is_control) {
+            url.chars().filter(|c| !c.is_control()).collect()
+        } else {
+            url
+        };
+        if !area.is_empty() && !url.is_empty() {
+            self.links.borrow_mut().push(Link { area, url });
+        }
+    }
+
+    /// Removes and returns the links added for the next frame.
+    pub(crate) fn take(&self) -> Vec<Link> {
+        std::mem::take(&mut self.links.borrow_mut())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Link {
+    pub(crate) area: Rect,
+    pub(crate) url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::layout::Rect;
+
+    use super::{Hyperlinks, Link};
+
+    #[test]
+    fn keeps_links_for_one_frame() {
+        let links = Hyperlinks::default();
+        let area = Rect::new(1, 2, 3, 1);
+        links.clone().add(area, "https://a");
+        assert_eq!(
+            links.take(),
+            [Link {
+                area,
+                url: "https://a".to_string(),
+            }]
+        );
+        assert!(links.take().is_empty());
+    }
+
+    #[test]
+    fn skips_control_characters_and_empty_links() {
+        let links = Hyperlinks::default();
+        links.add(Rect::new(0, 0, 1, 1), "https://a\x1B\\\x07b");
+        links.add(Rect::new(0, 0, 0, 1), "https://c");
+        links.add(Rect::new(0, 0, 1, 1), "\x07");
+        let urls: Vec<_> = links.take().into_iter().map(|link| link.url).collect();
+        assert_eq!(urls, ["https://a\\b"]);
+    }
+}
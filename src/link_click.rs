@@ -0,0 +1,39 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+
+use crate::queue::Queue;
+
+/// A click on a hyperlink, delivered when the terminal was created with
+/// [`LinkHandler::Event`](crate::LinkHandler::Event).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkClick {
+    /// The URL of the clicked link.
+    pub url: String,
+}
+
+/// Carries link clicks from xterm.js to a [`LinkClicks`] stream.
+pub(crate) type LinkQueue = Queue<LinkClick>;
+
+/// A stream of clicks on hyperlinks in a [`JsTerminal`](crate::JsTerminal).
+///
+/// Created by [`JsTerminal::link_clicks`](crate::JsTerminal::link_clicks). The stream ends when
+/// the terminal is disposed.
+pub struct LinkClicks {
+    queue: LinkQueue,
+}
+
+impl LinkClicks {
+    pub(crate) fn new(queue: LinkQueue) -> Self {
+        Self { queue }
+    }
+}
+
+impl Stream for LinkClicks {
+    type Item = LinkClick;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.queue.poll_next(cx)
+    }
+}
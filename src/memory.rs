@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use ratatui::backend::WindowSize;
+use ratatui::layout::Size;
+
+use crate::input::InputQueue;
+use crate::{EventStream, Host, InputBuffer, TerminalHandle, TerminalInput, TerminalQueries};
+
+/// An in-memory [`Host`] that records all output and lets the caller send input.
+///
+/// This works on every target, so it can be used to test apps natively without a browser. It
+/// doesn't interpret the output, so the cursor position only changes when it's set explicitly.
+///
+/// This is a cheaply cloneable reference. All clones refer to the same terminal.
+#[derive(Clone)]
+pub struct MemoryTerminal {
+    state: Rc<RefCell<MemoryState>>,
+    input: InputQueue,
+}
+
+struct MemoryState {
+    output: String,
+    cols: u16,
+    rows: u16,
+    cursor: (u16, u16),
+}
+
+impl MemoryTerminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(MemoryState {
+                output: String::new(),
+                cols,
+                rows,
+                cursor: (0, 0),
+            })),
+            input: InputQueue::new(InputBuffer::Unbounded),
+        }
+    }
+
+    /// Sets how much input is buffered before it's read. Defaults to
+    /// [`InputBuffer::Unbounded`].
+    pub fn with_input_buffer(mut self, input_buffer: InputBuffer) -> Self {
+        self.input = InputQueue::new(input_buffer);
+        self
+    }
+
+    /// Creates a new output handle that writes to this terminal.
+    pub fn handle(&self) -> TerminalHandle<Self> {
+        TerminalHandle::new(self.clone())
+    }
+
+    /// Creates a stream of input events from this terminal.
+    ///
+    /// Only one stream can read from the terminal at a time. See [`EventStream`].
+    pub fn event_stream(&self) -> EventStream<Self> {
+        EventStream::new(self.clone())
+    }
+
+    /// Creates a handle for querying this terminal.
+    ///
+    /// This terminal doesn't answer queries itself. Send the replies with
+    /// [`send_input`](Self::send_input) after the query was written.
+    pub fn queries(&self) -> TerminalQueries<Self> {
+        TerminalQueries::new(self.clone(), self.input.queries())
+    }
+
+    /// Returns everything written to the terminal so far.
+    pub fn output(&self) -> String {
+        self.state.borrow().output.clone()
+    }
+
+    /// Returns everything written to the terminal since the last call and clears it.
+    pub fn take_output(&self) -> String {
+        std::mem::take(&mut self.state.borrow_mut().output)
+    }
+
+    pub fn set_cursor_position(&self, x: u16, y: u16) {
+        self.state.borrow_mut().cursor = (x, y);
+    }
+
+    /// Changes the size of the terminal and sends a resize event.
+    pub fn resize(&self, cols: u16, rows: u16) {
+        let mut state = self.state.borrow_mut();
+        state.cols = cols;
+        state.rows = rows;
+        drop(state);
+        self.input.push(TerminalInput::Resize(cols, rows));
+    }
+
+    /// Sends input as if it was typed into the terminal.
+    pub fn send_input(&self, data: impl AsRef<[u8]>) {
+        self.input.push(TerminalInput::Data(data.as_ref().to_vec()));
+    }
+
+    /// Sends a focus change as if the terminal gained or lost focus.
+    pub fn set_focus(&self, focused: bool) {
+        self.input.push(TerminalInput::Focus(focused));
+    }
+
+    /// Returns the total number of inputs dropped because the input buffer was full.
+    pub fn dropped_input_count(&self) -> usize {
+        self.input.dropped()
+    }
+
+    /// Ends any event streams once the remaining input is read.
+    pub fn close(&self) {
+        self.input.close();
+    }
+}
+
+impl Host for MemoryTerminal {
+    fn write(&self, data: &str) -> io::Result<()> {
+        self.state.borrow_mut().output.push_str(data);
+        Ok(())
+    }
+
+    fn window_size(&self) -> io::Result<WindowSize> {
+        let state = self.state.borrow();
+        Ok(WindowSize {
+            columns_rows: Size::new(state.cols, state.rows),
+            pixels: Size::default(),
+        })
+    }
+
+    fn cursor_position(&self) -> io::Result<(u16, u16)> {
+        Ok(self.state.borrow().cursor)
+    }
+
+    fn poll_input(&self, cx: &mut Context<'_>) -> Poll<Option<TerminalInput>> {
+        self.input.poll_next(cx)
+    }
+}
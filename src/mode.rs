@@ -0,0 +1,51 @@
+/// A terminal mode that [`XtermJsBackend`](crate::XtermJsBackend) can turn on and restore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TerminalMode {
+    /// The alternate screen (DEC mode 1049), which is restored to the previous contents when
+    /// it's left.
+    AlternateScreen,
+    /// Reporting of mouse clicks, drags and movement (DEC modes 1000, 1002, 1003, 1015 and
+    /// 1006).
+    MouseCapture,
+    /// Bracketed paste (DEC mode 2004), so pasted text arrives as a single paste event.
+    BracketedPaste,
+    /// Reporting of focus changes (DEC mode 1004).
+    FocusReporting,
+    /// A hidden cursor (DEC mode 25 reset).
+    HiddenCursor,
+}
+
+impl TerminalMode {
+    /// All modes, in the order they're turned off when restoring a terminal whose state isn't
+    /// known.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) const ALL: [Self; 5] = [
+        Self::MouseCapture,
+        Self::BracketedPaste,
+        Self::FocusReporting,
+        Self::AlternateScreen,
+        Self::HiddenCursor,
+    ];
+
+    /// Returns the sequence that turns the mode on.
+    pub(crate) fn enable_sequence(self) -> &'static str {
+        match self {
+            Self::AlternateScreen => "\x1B[?1049h",
+            Self::MouseCapture => "\x1B[?1000h\x1B[?1002h\x1B[?1003h\x1B[?1015h\x1B[?1006h",
+            Self::BracketedPaste => "\x1B[?2004h",
+            Self::FocusReporting => "\x1B[?1004h",
+            Self::HiddenCursor => "\x1B[?25l",
+        }
+    }
+
+    /// Returns the sequence that turns the mode off.
+    pub(crate) fn disable_sequence(self) -> &'static str {
+        match self {
+            Self::AlternateScreen => "\x1B[?1049l",
+            Self::MouseCapture => "\x1B[?1006l\x1B[?1015l\x1B[?1003l\x1B[?1002l\x1B[?1000l",
+            Self::BracketedPaste => "\x1B[?2004l",
+            Self::FocusReporting => "\x1B[?1004l",
+            Self::HiddenCursor => "\x1B[?25h",
+        }
+    }
+}
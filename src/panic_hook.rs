@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::panic::PanicHookInfo;
+use std::sync::Once;
+
+use wasm_bindgen::JsValue;
+
+use crate::backend::END_SYNCHRONIZED_UPDATE;
+use crate::js_terminal::WeakJsTerminal;
+use crate::{JsTerminal, TerminalMode};
+
+thread_local! {
+    static PANIC_TERMINAL: RefCell<Option<WeakJsTerminal>> = const { RefCell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// Installs a panic hook that restores `terminal` and shows the panic in it.
+///
+/// On a panic, the hook leaves the alternate screen, shows the cursor, turns off mouse capture,
+/// bracketed paste and focus reporting, and prints the panic message and location. If the
+/// terminal was dropped or disposed, or writing to it fails, the panic is logged to the console
+/// instead. The hook that was installed before, such as `console_error_panic_hook`'s, still runs
+/// afterwards.
+///
+/// The hook only keeps a weak reference to the terminal and doesn't borrow any of its state, so
+/// it's safe to panic while the terminal is in use. Calling this again switches the hook to
+/// another terminal.
+pub fn install_panic_hook(terminal: &JsTerminal) {
+    PANIC_TERMINAL.with(|t| *t.borrow_mut() = Some(terminal.downgrade()));
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            panic_hook(info);
+            previous(info);
+        }));
+    });
+}
+
+fn panic_hook(info: &PanicHookInfo<'_>) {
+    let message = info.to_string();
+    let terminal = PANIC_TERMINAL
+        .try_with(|t| t.try_borrow().ok().and_then(|t| t.clone()))
+        .ok()
+        .flatten();
+    let shown = terminal.is_some_and(|t| t.write_now(&report(&message)).is_ok());
+    if !shown {
+        web_sys::console::error_1(&JsValue::from_str(&message));
+    }
+}
+
+/// Returns the output that restores the terminal and prints `message`.
+fn report(message: &str) -> String {
+    let mut report = String::from(END_SYNCHRONIZED_UPDATE);
+    // Close a hyperlink the panic may have interrupted, then reset the style
+    report.push_str("\x1B]8;;\x1B\\\x1B[m");
+    for mode in TerminalMode::ALL {
+        report.push_str(mode.disable_sequence());
+    }
+    // xterm.js doesn't return to the start of the line on a line feed by default
+    report.push_str("\r\n");
+    report.push_str(&message.replace('\n', "\r\n"));
+    report.push_str("\r\n");
+    report
+}
@@ -0,0 +1,511 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use futures::channel::oneshot;
+use ratatui::style::Color;
+
+use crate::Host;
+use crate::error::Error;
+
+const ESC: u8 = b'\x1B';
+const BEL: u8 = b'\x07';
+
+/// DEC private mode for synchronized output.
+const SYNCHRONIZED_OUTPUT_MODE: u16 = 2026;
+
+/// The state of a DEC private mode as reported by the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeStatus {
+    /// The terminal doesn't know the mode.
+    NotRecognized,
+    Set,
+    Reset,
+    PermanentlySet,
+    PermanentlyReset,
+}
+
+/// Sends queries to a terminal and waits for its replies.
+///
+/// Replies are taken out of the terminal's input as they arrive, so they never show up in an
+/// [`EventStream`](crate::EventStream). If the terminal is closed or disposed while waiting, the
+/// query fails.
+///
+/// Queries of the same kind are answered in the order they were sent.
+///
+/// # Timeouts
+///
+/// A query only completes once the terminal answers, and there is no timeout. A terminal that
+/// doesn't support a query never answers it, so the query waits forever. Unless the terminal is
+/// known to answer, as xterm.js does for all of these queries, race each query against a timer
+/// from your runtime. Dropping the future forgets the query, and a late reply is then passed on
+/// as input.
+pub struct TerminalQueries<H> {
+    terminal: H,
+    dispatcher: QueryDispatcher,
+}
+
+impl<H: Host> TerminalQueries<H> {
+    pub(crate) fn new(terminal: H, dispatcher: QueryDispatcher) -> Self {
+        Self {
+            terminal,
+            dispatcher,
+        }
+    }
+
+    pub fn terminal(&self) -> &H {
+        &self.terminal
+    }
+
+    /// Requests the cursor position (`CSI 6 n`) and returns it as a zero-based
+    /// `(column, row)`.
+    pub async fn cursor_position(&self) -> io::Result<(u16, u16)> {
+        self.query(QueryKind::CursorPosition, |reply| match reply {
+            Reply::CursorPosition(col, row) => Some((col, row)),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the primary device attributes (DA1, `CSI c`) and returns the reported parameters.
+    pub async fn device_attributes(&self) -> io::Result<Vec<u16>> {
+        self.query(QueryKind::PrimaryDeviceAttributes, |reply| match reply {
+            Reply::PrimaryDeviceAttributes(params) => Some(params),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the secondary device attributes (DA2, `CSI > c`) and returns the reported
+    /// parameters.
+    pub async fn secondary_device_attributes(&self) -> io::Result<Vec<u16>> {
+        self.query(QueryKind::SecondaryDeviceAttributes, |reply| match reply {
+            Reply::SecondaryDeviceAttributes(params) => Some(params),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the terminal's name and version (XTVERSION, `CSI > q`).
+    ///
+    /// Many terminals ignore this query, so race it against a timeout. See
+    /// [Timeouts](Self#timeouts).
+    pub async fn version(&self) -> io::Result<String> {
+        self.query(QueryKind::Version, |reply| match reply {
+            Reply::Version(version) => Some(version),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the default foreground color (`OSC 10`).
+    pub async fn foreground_color(&self) -> io::Result<Color> {
+        self.query(QueryKind::ForegroundColor, |reply| match reply {
+            Reply::ForegroundColor(color) => Some(color),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the default background color (`OSC 11`).
+    pub async fn background_color(&self) -> io::Result<Color> {
+        self.query(QueryKind::BackgroundColor, |reply| match reply {
+            Reply::BackgroundColor(color) => Some(color),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the state of a DEC private mode (DECRQM, `CSI ? mode $ p`).
+    pub async fn dec_mode(&self, mode: u16) -> io::Result<ModeStatus> {
+        self.query(QueryKind::DecMode(mode), |reply| match reply {
+            Reply::DecMode(_, status) => Some(status),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Returns whether the terminal supports synchronized output (DEC mode 2026).
+    ///
+    /// See [`XtermJsBackend::with_synchronized_output`](crate::XtermJsBackend::with_synchronized_output).
+    pub async fn supports_synchronized_output(&self) -> io::Result<bool> {
+        let status = self.dec_mode(SYNCHRONIZED_OUTPUT_MODE).await?;
+        Ok(matches!(
+            status,
+            ModeStatus::Set | ModeStatus::Reset | ModeStatus::PermanentlySet
+        ))
+    }
+
+    /// Sends the query and returns the value that `extract` takes out of the reply.
+    async fn query<T>(
+        &self,
+        kind: QueryKind,
+        extract: impl FnOnce(Reply) -> Option<T>,
+    ) -> io::Result<T> {
+        let reply = self.dispatcher.register(kind);
+        self.terminal.write(&kind.request())?;
+        // The sender is dropped without a reply once the terminal's input is closed
+        let reply = reply.await.map_err(|_| Error::Disposed)?;
+        // The dispatcher only hands out replies of the query's kind, so this shouldn't fail
+        extract(reply).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("received a reply of the wrong kind to a {kind:?} query"),
+            )
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum QueryKind {
+    CursorPosition,
+    PrimaryDeviceAttributes,
+    SecondaryDeviceAttributes,
+    Version,
+    ForegroundColor,
+    BackgroundColor,
+    DecMode(u16),
+}
+
+impl QueryKind {
+    fn request(self) -> Cow<'static, str> {
+        match self {
+            Self::CursorPosition => "\x1B[6n".into(),
+            Self::PrimaryDeviceAttributes => "\x1B[c".into(),
+            Self::SecondaryDeviceAttributes => "\x1B[>c".into(),
+            Self::Version => "\x1B[>0q".into(),
+            Self::ForegroundColor => "\x1B]10;?\x07".into(),
+            Self::BackgroundColor => "\x1B]11;?\x07".into(),
+            Self::DecMode(mode) => format!("\x1B[?{mode}$p").into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Reply {
+    /// Zero-based `(column, row)`.
+    CursorPosition(u16, u16),
+    PrimaryDeviceAttributes(Vec<u16>),
+    SecondaryDeviceAttributes(Vec<u16>),
+    Version(String),
+    ForegroundColor(Color),
+    BackgroundColor(Color),
+    DecMode(u16, ModeStatus),
+}
+
+impl Reply {
+    fn kind(&self) -> QueryKind {
+        match self {
+            Self::CursorPosition(..) => QueryKind::CursorPosition,
+            Self::PrimaryDeviceAttributes(_) => QueryKind::PrimaryDeviceAttributes,
+            Self::SecondaryDeviceAttributes(_) => QueryKind::SecondaryDeviceAttributes,
+            Self::Version(_) => QueryKind::Version,
+            Self::ForegroundColor(_) => QueryKind::ForegroundColor,
+            Self::BackgroundColor(_) => QueryKind::BackgroundColor,
+            Self::DecMode(mode, _) => QueryKind::DecMode(*mode),
+        }
+    }
+}
+
+/// Matches replies to terminal queries in the input and removes them before they reach the
+/// event stream.
+///
+/// Replies are only recognized while a query of the same kind is waiting, since some of them
+/// look like regular key presses.
+#[derive(Clone, Default)]
+pub(crate) struct QueryDispatcher {
+    pending: Rc<RefCell<Vec<PendingQuery>>>,
+}
+
+struct PendingQuery {
+    kind: QueryKind,
+    reply: oneshot::Sender<Reply>,
+}
+
+impl QueryDispatcher {
+    fn register(&self, kind: QueryKind) -> oneshot::Receiver<Reply> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .borrow_mut()
+            .push(PendingQuery { kind, reply: tx });
+        rx
+    }
+
+    /// Forgets queries whose caller stopped waiting, so their replies are passed on as input.
+    fn remove_canceled(&self) {
+        self.pending
+            .borrow_mut()
+            .retain(|query| !query.reply.is_canceled());
+    }
+
+    /// Drops all waiting queries.
+    pub(crate) fn cancel(&self) {
+        self.pending.borrow_mut().clear();
+    }
+
+    /// Removes any replies to waiting queries from `data`.
+    pub(crate) fn filter(&self, data: Vec<u8>) -> Vec<u8> {
+        self.remove_canceled();
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_empty() {
+            return data;
+        }
+
+        let mut remaining = Vec::with_capacity(data.len());
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] == ESC {
+                if let Some((len, reply)) = parse_reply(&data[i..]) {
+                    let kind = reply.kind();
+                    if let Some(index) = pending.iter().position(|query| query.kind == kind) {
+                        pending.remove(index).reply.send(reply).ok();
+                        i += len;
+                        continue;
+                    }
+                }
+            }
+            remaining.push(data[i]);
+            i += 1;
+        }
+        remaining
+    }
+}
+
+/// Parses a reply at the start of `data`, returning its length in bytes.
+fn parse_reply(data: &[u8]) -> Option<(usize, Reply)> {
+    match data.get(1)? {
+        b'[' => parse_csi_reply(data),
+        b'P' => parse_version(data),
+        b']' => parse_color(data),
+        _ => None,
+    }
+}
+
+fn parse_csi_reply(data: &[u8]) -> Option<(usize, Reply)> {
+    let end = data[2..].iter().position(|b| (0x40..=0x7E).contains(b))? + 2;
+    let params = std::str::from_utf8(&data[2..end]).ok()?;
+    let reply = match (data[end], params.as_bytes().first()) {
+        (b'R', Some(b'0'..=b'9')) => {
+            let (row, col) = params.split_once(';')?;
+            let row: u16 = row.parse().ok()?;
+            let col: u16 = col.parse().ok()?;
+            Reply::CursorPosition(col.saturating_sub(1), row.saturating_sub(1))
+        }
+        (b'c', Some(b'?')) => Reply::PrimaryDeviceAttributes(parse_params(&params[1..])?),
+        (b'c', Some(b'>')) => Reply::SecondaryDeviceAttributes(parse_params(&params[1..])?),
+        // DECRPM: CSI ? mode ; status $ y
+        (b'y', Some(b'?')) => {
+            let (mode, status) = params[1..].strip_suffix('$')?.split_once(';')?;
+            let status = match status {
+                "0" => ModeStatus::NotRecognized,
+                "1" => ModeStatus::Set,
+                "2" => ModeStatus::Reset,
+                "3" => ModeStatus::PermanentlySet,
+                "4" => ModeStatus::PermanentlyReset,
+                _ => return None,
+            };
+            Reply::DecMode(mode.parse().ok()?, status)
+        }
+        _ => return None,
+    };
+    Some((end + 1, reply))
+}
+
+fn parse_params(params: &str) -> Option<Vec<u16>> {
+    params.split(';').map(|p| p.parse().ok()).collect()
+}
+
+/// Parses `DCS > | text ST`.
+fn parse_version(data: &[u8]) -> Option<(usize, Reply)> {
+    let body = data.strip_prefix(b"\x1BP>|")?;
+    let end = body.windows(2).position(|w| w == b"\x1B\\")?;
+    let version = String::from_utf8_lossy(&body[..end]).into_owned();
+    Some((4 + end + 2, Reply::Version(version)))
+}
+
+/// Parses `OSC 10 ; rgb:rrrr/gggg/bbbb ST` and the equivalent reply for OSC 11.
+fn parse_color(data: &[u8]) -> Option<(usize, Reply)> {
+    let (end, terminator_len) = data.iter().enumerate().find_map(|(i, b)| match *b {
+        BEL => Some((i, 1)),
+        ESC if data.get(i + 1) == Some(&b'\\') => Some((i, 2)),
+        _ => None,
+    })?;
+    let body = std::str::from_utf8(&data[2..end]).ok()?;
+    let (code, spec) = body.split_once(';')?;
+    let mut channels = spec.strip_prefix("rgb:")?.split('/').map(|c| {
+        // Channels may have 1-4 hex digits, which are scaled to a byte
+        if !(1..=4).contains(&c.len()) {
+            return None;
+        }
+        let value = u16::from_str_radix(c, 16).ok()?;
+        let max = (1u32 << (4 * c.len() as u32)) - 1;
+        Some((value as u32 * 255 / max) as u8)
+    });
+    let color = Color::Rgb(channels.next()??, channels.next()??, channels.next()??);
+    let reply = match code {
+        "10" => Reply::ForegroundColor(color),
+        "11" => Reply::BackgroundColor(color),
+        _ => return None,
+    };
+    Some((end + terminator_len, reply))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use futures::executor::block_on;
+    use futures::{StreamExt, join};
+    use ratatui::style::Color;
+    use terminput::{Event, KeyCode};
+
+    use super::{ModeStatus, QueryDispatcher, QueryKind, Reply, parse_reply};
+    use crate::MemoryTerminal;
+
+    #[test]
+    fn parses_cursor_position() {
+        assert_eq!(
+            parse_reply(b"\x1B[12;40Rx"),
+            Some((8, Reply::CursorPosition(39, 11)))
+        );
+    }
+
+    #[test]
+    fn parses_device_attributes() {
+        assert_eq!(
+            parse_reply(b"\x1B[?62;22c"),
+            Some((9, Reply::PrimaryDeviceAttributes(vec![62, 22])))
+        );
+        assert_eq!(
+            parse_reply(b"\x1B[>0;276;0c"),
+            Some((11, Reply::SecondaryDeviceAttributes(vec![0, 276, 0])))
+        );
+    }
+
+    #[test]
+    fn parses_version() {
+        assert_eq!(
+            parse_reply(b"\x1BP>|xterm.js(5.5.0)\x1B\\"),
+            Some((21, Reply::Version("xterm.js(5.5.0)".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_colors_ending_in_bel_or_st() {
+        assert_eq!(
+            parse_reply(b"\x1B]10;rgb:ffff/8080/0000\x07"),
+            Some((24, Reply::ForegroundColor(Color::Rgb(255, 128, 0))))
+        );
+        assert_eq!(
+            parse_reply(b"\x1B]11;rgb:ff/80/00\x1B\\"),
+            Some((19, Reply::BackgroundColor(Color::Rgb(255, 128, 0))))
+        );
+        assert_eq!(
+            parse_reply(b"\x1B]11;rgb:f/0/8\x07"),
+            Some((15, Reply::BackgroundColor(Color::Rgb(255, 0, 136))))
+        );
+    }
+
+    #[test]
+    fn parses_mode_reports() {
+        assert_eq!(
+            parse_reply(b"\x1B[?2026;2$y"),
+            Some((11, Reply::DecMode(2026, ModeStatus::Reset)))
+        );
+        assert_eq!(
+            parse_reply(b"\x1B[?1049;0$y"),
+            Some((11, Reply::DecMode(1049, ModeStatus::NotRecognized)))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_replies() {
+        for data in [
+            &b"\x1B[A"[..],
+            b"\x1B[12;4",
+            b"\x1B[12R",
+            b"\x1B[?2026;9$y",
+            b"\x1B[?2026$y",
+            b"\x1BP>|unterminated",
+            b"\x1B]10;rgb:ff/ff\x07",
+            b"\x1B]10;rgb:gg/0/0\x07",
+            b"\x1B]10;rgb:/0/0\x07",
+            b"\x1B]10;rgb:00000000/0/0\x07",
+            b"\x1B]10;rgb:00001/0/0\x07",
+            b"\x1B]12;rgb:f/f/f\x07",
+            b"\x1B]10;rgb:f/f/f",
+            b"\x1B",
+        ] {
+            assert_eq!(
+                parse_reply(data),
+                None,
+                "{:?}",
+                String::from_utf8_lossy(data)
+            );
+        }
+    }
+
+    #[test]
+    fn removes_replies_from_keystrokes() {
+        let dispatcher = QueryDispatcher::default();
+        let mut reply = dispatcher.register(QueryKind::CursorPosition);
+        assert_eq!(
+            dispatcher.filter(b"a\x1B[3;4Rb\x1B[A".to_vec()),
+            b"ab\x1B[A"
+        );
+        assert_eq!(reply.try_recv(), Ok(Some(Reply::CursorPosition(3, 2))));
+
+        // Without a waiting query, the same bytes are input
+        assert_eq!(dispatcher.filter(b"\x1B[3;4R".to_vec()), b"\x1B[3;4R");
+    }
+
+    #[test]
+    fn only_removes_replies_to_waiting_queries() {
+        let dispatcher = QueryDispatcher::default();
+        let _reply = dispatcher.register(QueryKind::PrimaryDeviceAttributes);
+        assert_eq!(dispatcher.filter(b"\x1B[3;4R".to_vec()), b"\x1B[3;4R");
+
+        let reply = dispatcher.register(QueryKind::CursorPosition);
+        drop(reply);
+        assert_eq!(dispatcher.filter(b"\x1B[3;4R".to_vec()), b"\x1B[3;4R");
+    }
+
+    #[test]
+    fn answers_queries_and_passes_keystrokes_on() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let queries = terminal.queries();
+        let (version, ()) = block_on(async {
+            join!(queries.version(), async {
+                terminal.send_input("q\x1BP>|xterm.js(5.5.0)\x1B\\");
+            })
+        });
+        assert_eq!(version.unwrap(), "xterm.js(5.5.0)");
+        assert_eq!(terminal.take_output(), "\x1B[>0q");
+
+        terminal.close();
+        let events: Vec<_> = block_on(terminal.event_stream().map(Result::unwrap).collect());
+        assert!(matches!(&events[..], [Event::Key(key)] if key.code == KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn fails_queries_when_closed() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let queries = terminal.queries();
+        let (position, ()) =
+            block_on(async { join!(queries.cursor_position(), async { terminal.close() }) });
+        assert!(position.is_err());
+    }
+
+    #[test]
+    fn fails_queries_with_replies_of_the_wrong_kind() {
+        let terminal = MemoryTerminal::new(80, 24);
+        let queries = terminal.queries();
+        let (result, ()) = block_on(async {
+            join!(queries.query(QueryKind::Version, |_| None::<()>), async {
+                terminal.send_input("\x1BP>|xterm.js(5.5.0)\x1B\\");
+            })
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
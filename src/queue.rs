@@ -0,0 +1,139 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+struct State<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    /// The waker of the reader that polled last.
+    waker: Option<Waker>,
+}
+
+/// Single-threaded queue that carries items from callbacks to a single reader.
+///
+/// Clones share the same queue. Only the reader that polled last is woken.
+pub(crate) struct Queue<T>(Rc<RefCell<State<T>>>);
+
+impl<T> Queue<T> {
+    pub(crate) fn new() -> Self {
+        Self(Rc::new(RefCell::new(State {
+            items: VecDeque::new(),
+            closed: false,
+            waker: None,
+        })))
+    }
+
+    #[cfg(any(target_arch = "wasm32", test))]
+    pub(crate) fn push(&self, item: T) {
+        self.update(|items| items.push_back(item));
+    }
+
+    /// Changes the queued items with `f` and wakes the reader. Does nothing once the queue is
+    /// closed.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut VecDeque<T>)) {
+        let mut state = self.0.borrow_mut();
+        if state.closed {
+            return;
+        }
+        f(&mut state.items);
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.borrow().closed
+    }
+
+    /// Closes the queue. Remaining items can still be read, after which the queue ends.
+    pub(crate) fn close(&self) {
+        let mut state = self.0.borrow_mut();
+        state.closed = true;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.0.borrow_mut();
+        match state.items.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context, Poll};
+
+    use super::Queue;
+    use crate::test_util::counting_waker;
+
+    #[test]
+    fn delivers_items_in_order() {
+        let queue = Queue::new();
+        let reader = queue.clone();
+        queue.push(1);
+        queue.update(|items| items.extend([2, 3]));
+        let (count, waker) = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(reader.poll_next(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(reader.poll_next(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(reader.poll_next(&mut cx), Poll::Ready(Some(3)));
+        assert_eq!(reader.poll_next(&mut cx), Poll::Pending);
+        assert_eq!(count.get(), 0);
+    }
+
+    #[test]
+    fn wakes_the_reader() {
+        let queue = Queue::new();
+        let (count, waker) = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(queue.poll_next(&mut cx), Poll::Pending);
+        queue.push(1);
+        queue.push(2);
+        // The reader is woken once until it polls again
+        assert_eq!(count.get(), 1);
+        assert_eq!(queue.poll_next(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(queue.poll_next(&mut cx), Poll::Ready(Some(2)));
+
+        assert_eq!(queue.poll_next(&mut cx), Poll::Pending);
+        queue.close();
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn ends_after_the_remaining_items_once_closed() {
+        let queue = Queue::new();
+        queue.push(1);
+        queue.close();
+        assert!(queue.is_closed());
+        queue.push(2);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert_eq!(queue.poll_next(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(queue.poll_next(&mut cx), Poll::Ready(None));
+    }
+}
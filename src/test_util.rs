@@ -0,0 +1,27 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Waker;
+
+use futures::task::{ArcWake, waker};
+
+/// Counts how often a waker was woken.
+#[derive(Default)]
+pub(crate) struct WakeCount(AtomicUsize);
+
+impl WakeCount {
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl ArcWake for WakeCount {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Returns a waker and the count of how often it was woken.
+pub(crate) fn counting_waker() -> (Arc<WakeCount>, Waker) {
+    let count = Arc::new(WakeCount::default());
+    (count.clone(), waker(count))
+}
@@ -0,0 +1,451 @@
+use std::path::Path;
+use std::{fmt, io};
+
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+use ratatui::{Frame, TerminalOptions};
+
+use crate::ansi::ansi_index;
+use crate::{EmulatedTerminal, Host, XtermJsBackend};
+
+/// Environment variable that makes [`ScreenHarness::assert_snapshot_file`] overwrite snapshots
+/// instead of comparing against them.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "UPDATE_SNAPSHOTS";
+
+/// Modifiers tracked by the emulator. Other modifiers are ignored when comparing cells.
+const COMPARED_MODIFIERS: Modifier = Modifier::BOLD
+    .union(Modifier::ITALIC)
+    .union(Modifier::UNDERLINED)
+    .union(Modifier::REVERSED);
+
+/// Format used for screen snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// The visible text, one line per row.
+    Text,
+    /// The visible text including the escape sequences needed to reproduce its styles.
+    Ansi,
+}
+
+/// Drives a ratatui [`Terminal`](ratatui::Terminal) backed by an [`EmulatedTerminal`] so the
+/// emulated screen can be compared with what ratatui drew.
+pub struct ScreenHarness {
+    emulator: EmulatedTerminal,
+    terminal: ratatui::Terminal<XtermJsBackend<EmulatedTerminal>>,
+    last_frame: Option<Buffer>,
+}
+
+impl ScreenHarness {
+    /// Creates a harness with a fullscreen viewport.
+    pub fn new(cols: u16, rows: u16) -> io::Result<Self> {
+        Self::with_options(cols, rows, TerminalOptions::default())
+    }
+
+    /// Creates a harness using the given options, for example to test an inline viewport.
+    pub fn with_options(cols: u16, rows: u16, options: TerminalOptions) -> io::Result<Self> {
+        Self::with_emulator(EmulatedTerminal::new(cols, rows), options)
+    }
+
+    /// Creates a harness that draws to an existing emulator.
+    pub fn with_emulator(emulator: EmulatedTerminal, options: TerminalOptions) -> io::Result<Self> {
+        let terminal =
+            ratatui::Terminal::with_options(XtermJsBackend::new(emulator.handle()), options)?;
+        Ok(Self {
+            emulator,
+            terminal,
+            last_frame: None,
+        })
+    }
+
+    pub fn emulator(&self) -> &EmulatedTerminal {
+        &self.emulator
+    }
+
+    pub fn terminal(&mut self) -> &mut ratatui::Terminal<XtermJsBackend<EmulatedTerminal>> {
+        &mut self.terminal
+    }
+
+    /// Draws a frame and remembers the buffer ratatui drew so it can be compared later.
+    pub fn draw<F>(&mut self, render: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Frame),
+    {
+        let frame = self.terminal.draw(render)?;
+        self.last_frame = Some(frame.buffer.clone());
+        Ok(())
+    }
+
+    /// Returns the buffer from the last call to [`draw`](Self::draw).
+    pub fn last_frame(&self) -> Option<&Buffer> {
+        self.last_frame.as_ref()
+    }
+
+    /// Converts the emulated screen to a buffer covering `area`.
+    pub fn screen_buffer(&self, area: Rect) -> Buffer {
+        let screen = self.emulator.screen();
+        let mut buffer = Buffer::empty(area);
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let Some(cell) = screen.cell(y, x) else {
+                    continue;
+                };
+                // ratatui resets the cells hidden behind a wide character
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                buffer[(x, y)] = to_cell(cell);
+            }
+        }
+        buffer
+    }
+
+    /// Compares the emulated screen with the buffer from the last call to
+    /// [`draw`](Self::draw).
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`draw`](Self::draw) hasn't been called yet.
+    pub fn compare_with_last_frame(&self) -> Result<(), ScreenMismatch> {
+        let expected = self
+            .last_frame
+            .as_ref()
+            .expect("draw must be called before comparing frames");
+        self.compare_with(expected)
+    }
+
+    /// Compares the emulated screen with `expected` within the buffer's area.
+    ///
+    /// Only the symbol, colors and the modifiers tracked by the emulator (bold, italic,
+    /// underlined and reversed) are compared.
+    pub fn compare_with(&self, expected: &Buffer) -> Result<(), ScreenMismatch> {
+        let actual = self.screen_buffer(expected.area);
+        let mut mismatches = Vec::new();
+        for y in expected.area.top()..expected.area.bottom() {
+            for x in expected.area.left()..expected.area.right() {
+                let expected_cell = &expected[(x, y)];
+                if expected_cell.skip {
+                    continue;
+                }
+                let expected_state = CellState::new(expected_cell);
+                let actual_state = CellState::new(&actual[(x, y)]);
+                if expected_state != actual_state {
+                    mismatches.push(CellMismatch {
+                        x,
+                        y,
+                        expected: expected_state,
+                        actual: actual_state,
+                    });
+                }
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(ScreenMismatch { mismatches })
+        }
+    }
+
+    /// Panics if the emulated screen differs from the buffer from the last call to
+    /// [`draw`](Self::draw).
+    ///
+    /// # Panics
+    ///
+    /// Panics with the differing cells if the screen doesn't match, or if
+    /// [`draw`](Self::draw) hasn't been called yet.
+    #[track_caller]
+    pub fn assert_matches_last_frame(&self) {
+        if let Err(e) = self.compare_with_last_frame() {
+            panic!("{e}");
+        }
+    }
+
+    /// Returns a snapshot of the emulated screen.
+    pub fn snapshot(&self, format: SnapshotFormat) -> String {
+        let screen = self.emulator.screen();
+        match format {
+            SnapshotFormat::Text => {
+                let (_, cols) = screen.size();
+                screen.rows(0, cols).collect::<Vec<_>>().join("\n")
+            }
+            SnapshotFormat::Ansi => String::from_utf8_lossy(&screen.contents_formatted()).into(),
+        }
+    }
+
+    /// Panics if the snapshot of the emulated screen differs from `expected`.
+    #[track_caller]
+    pub fn assert_snapshot(&self, format: SnapshotFormat, expected: &str) {
+        let actual = self.snapshot(format);
+        assert_eq!(actual, expected, "screen snapshot differs");
+    }
+
+    /// Panics if the snapshot of the emulated screen differs from the snapshot stored at `path`.
+    ///
+    /// If the file doesn't exist or [`UPDATE_SNAPSHOTS_VAR`] is set, the snapshot is written
+    /// instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the snapshots differ, or if the snapshot file can't be read or written.
+    #[track_caller]
+    pub fn assert_snapshot_file(&self, format: SnapshotFormat, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let actual = self.snapshot(format);
+        if std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() || !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).expect("failed to create snapshot directory");
+            }
+            std::fs::write(path, actual).expect("failed to write snapshot");
+            return;
+        }
+        let expected = std::fs::read_to_string(path).expect("failed to read snapshot");
+        assert_eq!(
+            actual,
+            expected,
+            "screen snapshot differs from {}",
+            path.display()
+        );
+    }
+
+    /// Returns the cursor position reported by the emulator as `(column, row)`.
+    pub fn cursor_position(&self) -> io::Result<(u16, u16)> {
+        self.emulator.cursor_position()
+    }
+}
+
+fn to_cell(cell: &vt100::Cell) -> Cell {
+    let mut modifier = Modifier::empty();
+    modifier.set(Modifier::BOLD, cell.bold());
+    modifier.set(Modifier::ITALIC, cell.italic());
+    modifier.set(Modifier::UNDERLINED, cell.underline());
+    modifier.set(Modifier::REVERSED, cell.inverse());
+
+    let mut result = Cell::default();
+    if cell.has_contents() {
+        result.set_symbol(&cell.contents());
+    }
+    result.set_fg(to_color(cell.fgcolor()));
+    result.set_bg(to_color(cell.bgcolor()));
+    result.modifier = modifier;
+    result
+}
+
+fn to_color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(i) => Color::Indexed(i),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+/// Maps the named ANSI colors to their index so equivalent colors compare equal.
+fn normalize_color(color: Color) -> Color {
+    ansi_index(color).map_or(color, Color::Indexed)
+}
+
+/// The parts of a cell that can be compared with the emulated screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellState {
+    pub symbol: String,
+    pub fg: Color,
+    pub bg: Color,
+    pub modifier: Modifier,
+}
+
+impl CellState {
+    fn new(cell: &Cell) -> Self {
+        Self {
+            symbol: cell.symbol().to_string(),
+            fg: normalize_color(cell.fg),
+            bg: normalize_color(cell.bg),
+            modifier: cell.modifier.intersection(COMPARED_MODIFIERS),
+        }
+    }
+}
+
+/// A cell where the emulated screen differs from the expected buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellMismatch {
+    pub x: u16,
+    pub y: u16,
+    pub expected: CellState,
+    pub actual: CellState,
+}
+
+/// Every cell where the emulated screen differs from the expected buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScreenMismatch {
+    pub mismatches: Vec<CellMismatch>,
+}
+
+impl fmt::Display for ScreenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "emulated screen differs from the expected buffer in {} cell(s):",
+            self.mismatches.len()
+        )?;
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "  ({}, {}): expected {:?}, got {:?}",
+                mismatch.x, mismatch.y, mismatch.expected, mismatch.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ScreenMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::style::{Color, Modifier, Style, Stylize};
+    use ratatui::text::{Line, Text};
+    use ratatui::widgets::{Block, Paragraph, Widget};
+    use ratatui::{TerminalOptions, Viewport};
+
+    use super::{CellState, ScreenHarness, SnapshotFormat};
+
+    #[test]
+    fn matches_the_drawn_frame() {
+        let mut harness = ScreenHarness::new(12, 2).unwrap();
+        harness
+            .draw(|frame| {
+                let line = Line::from_iter(["bold".bold(), " ".into(), "red".red().on_blue()]);
+                frame.render_widget(Paragraph::new(line), frame.area());
+            })
+            .unwrap();
+        harness.assert_matches_last_frame();
+        harness.assert_snapshot(SnapshotFormat::Text, "bold red\n");
+    }
+
+    #[test]
+    fn reports_cells_that_differ() {
+        let mut harness = ScreenHarness::new(4, 1).unwrap();
+        harness
+            .draw(|frame| frame.render_widget(Paragraph::new("ab"), frame.area()))
+            .unwrap();
+        let mut expected = Buffer::empty(Rect::new(0, 0, 4, 1));
+        expected.set_string(0, 0, "ax", Style::new().add_modifier(Modifier::BOLD));
+
+        let mismatch = harness.compare_with(&expected).unwrap_err();
+        let positions: Vec<_> = mismatch.mismatches.iter().map(|m| (m.x, m.y)).collect();
+        assert_eq!(positions, [(0, 0), (1, 0)]);
+        assert_eq!(
+            mismatch.mismatches[1].expected,
+            CellState {
+                symbol: "x".to_string(),
+                fg: Color::Reset,
+                bg: Color::Reset,
+                modifier: Modifier::BOLD,
+            }
+        );
+        assert_eq!(mismatch.mismatches[1].actual.symbol, "b");
+    }
+
+    #[test]
+    fn treats_named_and_indexed_colors_as_equal() {
+        let mut harness = ScreenHarness::new(4, 1).unwrap();
+        harness
+            .draw(|frame| frame.render_widget(Paragraph::new("a".light_red()), frame.area()))
+            .unwrap();
+        let mut expected = Buffer::empty(Rect::new(0, 0, 4, 1));
+        expected.set_string(0, 0, "a", Style::new().fg(Color::Indexed(9)));
+        harness.compare_with(&expected).unwrap();
+    }
+
+    #[test]
+    fn writes_and_compares_snapshot_files() {
+        let path = std::env::temp_dir()
+            .join(format!("ratatui-xterm-js-{}", std::process::id()))
+            .join("snapshot.txt");
+        let mut harness = ScreenHarness::new(4, 1).unwrap();
+        harness
+            .draw(|frame| frame.render_widget(Paragraph::new("ab"), frame.area()))
+            .unwrap();
+        harness.assert_snapshot_file(SnapshotFormat::Text, &path);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ab");
+        harness.assert_snapshot_file(SnapshotFormat::Text, &path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn round_trips_changing_frames() {
+        let mut harness = ScreenHarness::new(20, 4).unwrap();
+        let frames = [
+            Text::from(vec![
+                Line::from_iter(["bold".bold(), " under".underlined(), " 中文".red()]),
+                Line::from(" ".repeat(20).on_blue()),
+                Line::from("a".repeat(15).italic()),
+            ]),
+            Text::from(vec![
+                Line::from_iter(["bold".reversed(), " under".on_green(), "文".red()]),
+                Line::from_iter([" ".repeat(8).on_blue(), "x".into()]),
+                Line::from("a".repeat(5).light_cyan()),
+                Line::from("last column".blue().on_light_yellow()).right_aligned(),
+            ]),
+            Text::from(vec![
+                Line::from("中文中文中文中文中文中文"),
+                Line::default(),
+            ]),
+            Text::default(),
+        ];
+        for text in frames {
+            harness
+                .draw(|frame| frame.render_widget(Paragraph::new(text), frame.area()))
+                .unwrap();
+            harness.assert_matches_last_frame();
+        }
+    }
+
+    #[test]
+    fn round_trips_bordered_widgets() {
+        let mut harness = ScreenHarness::new(12, 5).unwrap();
+        for title in ["one", "two", "three"] {
+            harness
+                .draw(|frame| {
+                    let block = Block::bordered().title(title.bold()).on_dark_gray();
+                    frame.render_widget(Paragraph::new(title).block(block), frame.area());
+                })
+                .unwrap();
+            harness.assert_matches_last_frame();
+        }
+    }
+
+    #[test]
+    fn round_trips_lines_inserted_above_an_inline_viewport() {
+        let options = TerminalOptions {
+            viewport: Viewport::Inline(2),
+        };
+        let mut harness = ScreenHarness::with_options(10, 6, options).unwrap();
+        for i in 0..4 {
+            harness
+                .terminal()
+                .insert_before(1, |buf| {
+                    Line::from(format!("line {i}")).render(buf.area, buf)
+                })
+                .unwrap();
+            harness
+                .draw(|frame| {
+                    let status = Paragraph::new(format!("status {i}")).on_blue();
+                    frame.render_widget(status, frame.area());
+                })
+                .unwrap();
+            harness.assert_matches_last_frame();
+        }
+        harness.assert_snapshot(
+            SnapshotFormat::Text,
+            "line 0\nline 1\nline 2\nline 3\nstatus 3\n",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "draw must be called before comparing frames")]
+    fn panics_when_comparing_before_drawing() {
+        let harness = ScreenHarness::new(4, 1).unwrap();
+        harness.compare_with_last_frame().ok();
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::{Cell, RefCell};
+use std::task::{Context, Poll, Waker};
+
+/// Watermarks for the amount of output xterm.js may have queued, in bytes.
+///
+/// Once more than `high` bytes are waiting to be parsed,
+/// [`TerminalHandle::writable`](crate::TerminalHandle::writable) and the handle's
+/// [`AsyncWrite`](futures::io::AsyncWrite) implementation wait until xterm.js has worked through
+/// the queue down to `low` bytes. Writes through [`std::io::Write`] are never held back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowControl {
+    pub high: usize,
+    pub low: usize,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self {
+            high: 128 * 1024,
+            low: 16 * 1024,
+        }
+    }
+}
+
+/// Tracks writes that xterm.js hasn't finished parsing yet.
+pub(crate) struct PendingWrites {
+    count: Cell<usize>,
+    bytes: Cell<usize>,
+    flow_control: FlowControl,
+    /// Set once `bytes` exceeds the high watermark, until it drops to the low watermark.
+    throttled: Cell<bool>,
+    wakers: RefCell<Vec<Waker>>,
+    ready_wakers: RefCell<Vec<Waker>>,
+}
+
+impl PendingWrites {
+    pub(crate) fn new(flow_control: FlowControl) -> Self {
+        Self {
+            count: Cell::new(0),
+            bytes: Cell::new(0),
+            flow_control,
+            throttled: Cell::new(false),
+            wakers: RefCell::default(),
+            ready_wakers: RefCell::default(),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn flow_control(&self) -> FlowControl {
+        self.flow_control
+    }
+
+    /// Returns whether all writes have been parsed.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.count.get() == 0
+    }
+
+    pub(crate) fn start(&self, len: usize) {
+        self.count.set(self.count.get() + 1);
+        self.bytes.set(self.bytes.get() + len);
+        if self.bytes.get() > self.flow_control.high {
+            self.throttled.set(true);
+        }
+    }
+
+    pub(crate) fn finish(&self, len: usize) {
+        let count = self.count.get().saturating_sub(1);
+        self.count.set(count);
+        self.bytes.set(self.bytes.get().saturating_sub(len));
+        if self.throttled.get() && self.bytes.get() <= self.flow_control.low {
+            self.throttled.set(false);
+            for waker in self.ready_wakers.take() {
+                waker.wake();
+            }
+        }
+        if count == 0 {
+            for waker in self.wakers.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Forgets all pending writes, for when xterm.js will never report them as finished.
+    pub(crate) fn reset(&self) {
+        self.count.set(0);
+        self.bytes.set(0);
+        self.throttled.set(false);
+        for waker in self
+            .wakers
+            .take()
+            .into_iter()
+            .chain(self.ready_wakers.take())
+        {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.throttled.get() {
+            return Poll::Ready(());
+        }
+        register(&self.ready_wakers, cx.waker());
+        Poll::Pending
+    }
+
+    pub(crate) fn poll_done(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.count.get() == 0 {
+            return Poll::Ready(());
+        }
+        register(&self.wakers, cx.waker());
+        Poll::Pending
+    }
+}
+
+/// Adds `waker` to `wakers`, unless a waker for the same task is already waiting.
+fn register(wakers: &RefCell<Vec<Waker>>, waker: &Waker) {
+    let mut wakers = wakers.borrow_mut();
+    if !wakers.iter().any(|w| w.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context, Poll};
+
+    use super::{FlowControl, PendingWrites};
+    use crate::test_util::counting_waker;
+
+    fn writes() -> PendingWrites {
+        PendingWrites::new(FlowControl { high: 10, low: 5 })
+    }
+
+    #[test]
+    fn throttles_between_the_watermarks() {
+        let writes = writes();
+        let (count, waker) = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        writes.start(8);
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Ready(()));
+        writes.start(4);
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Pending);
+
+        // 8 bytes are still above the low watermark
+        writes.finish(4);
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(count.get(), 0);
+
+        writes.finish(8);
+        assert_eq!(count.get(), 1);
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Ready(()));
+    }
+
+    #[test]
+    fn waits_until_every_write_is_done() {
+        let writes = writes();
+        let (count, waker) = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        writes.start(1);
+        writes.start(1);
+        assert!(!writes.is_empty());
+        assert_eq!(writes.poll_done(&mut cx), Poll::Pending);
+        writes.finish(1);
+        assert_eq!(count.get(), 0);
+        writes.finish(1);
+        assert_eq!(count.get(), 1);
+        assert!(writes.is_empty());
+        assert_eq!(writes.poll_done(&mut cx), Poll::Ready(()));
+    }
+
+    #[test]
+    fn keeps_one_waker_per_task() {
+        let writes = writes();
+        let (count, waker) = counting_waker();
+        let (other_count, other_waker) = counting_waker();
+        writes.start(20);
+        for _ in 0..3 {
+            assert_eq!(
+                writes.poll_ready(&mut Context::from_waker(&waker)),
+                Poll::Pending
+            );
+            assert_eq!(
+                writes.poll_done(&mut Context::from_waker(&waker)),
+                Poll::Pending
+            );
+        }
+        assert_eq!(
+            writes.poll_ready(&mut Context::from_waker(&other_waker)),
+            Poll::Pending
+        );
+        assert_eq!(writes.ready_wakers.borrow().len(), 2);
+        assert_eq!(writes.wakers.borrow().len(), 1);
+
+        writes.finish(20);
+        assert_eq!(count.get(), 2);
+        assert_eq!(other_count.get(), 1);
+    }
+
+    #[test]
+    fn reset_wakes_every_waiter() {
+        let writes = writes();
+        let (count, waker) = counting_waker();
+        let mut cx = Context::from_waker(&waker);
+        writes.start(20);
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(writes.poll_done(&mut cx), Poll::Pending);
+
+        writes.reset();
+        assert_eq!(count.get(), 2);
+        assert!(writes.is_empty());
+        assert_eq!(writes.poll_ready(&mut cx), Poll::Ready(()));
+    }
+}